@@ -1,17 +1,62 @@
 use egui::{Id, Margin, Pos2, UiKind};
 use rfd::FileDialog;
 use uuid::Uuid;
-use crate::core::map::{EdgeType, Node, Tag};
+use crate::core::map::{ArrowStyle, DashStyle, Edge, EdgeType, Frame, Node, Tag};
 use crate::core::MindMap;
-use crate::core::storage::{export_project, load_last_file, load_map, save_last_file, save_map};
+use crate::core::storage::{export_project, import_project, load_last_file, load_map_file, save_last_file, save_map};
 use crate::core::pdfparser::Metadata;
-
+use crate::core::undo::{Command, History};
+use crate::core::search::{spawn_indexer, SearchHit, SearchIndex};
+use crate::core::references::{self, Format};
+use crate::core::layout::{self, ForceLayout};
+use crate::core::citations::{self, LinkProposal};
+use crate::core::identifiers;
+use crate::core::duplicates::{self, DuplicateGroup};
+use crate::core::analytics::{self, Analytics, Metric};
+use crate::core::spatial::{Bounds, Grid};
+
+// Simulation iterations run per frame while a layout animates
+const LAYOUT_STEPS_PER_FRAME: usize = 3;
+// Extra size of the top node when nodes are scaled by a metric
+const NODE_SCALE_RANGE: f32 = 1.0;
+// Rows listed per ranking in the analytics panel
+const ANALYTICS_TOP: usize = 10;
+// Edge labels are hidden below this zoom, where they would be unreadable
+const LABEL_MIN_ZOOM: f32 = 0.5;
+// Size of the minimap and its distance from the corner of the canvas
+const MINIMAP_SIZE: egui::Vec2 = egui::vec2(220.0, 160.0);
+const MINIMAP_MARGIN: f32 = 12.0;
+// Screen distance by which icons may stick out of a node
+const NODE_ICON_MARGIN: f32 = 32.0;
+// Width of the cells of the spatial index, about the size of a node
+const GRID_CELL_SIZE: f32 = 256.0;
+// Distance between edges joining the same two nodes
+const EDGE_FAN_SPACING: f32 = 24.0;
+// How far curved edges bow out, relative to their length
+const EDGE_CURVE_BEND: f32 = 0.15;
+// Straight pieces a curved edge is drawn with
+const CURVE_SEGMENTS: usize = 24;
+// Grid lines closer than this on screen are thinned out
+const GRID_MIN_SPACING: f32 = 8.0;
+// Screen distance within which a dragged node lines up with another node
+const GUIDE_SNAP_DISTANCE: f32 = 6.0;
+// Space between a frame and its nodes, and height of the strip holding its label
+const FRAME_PADDING: f32 = 24.0;
+const FRAME_LABEL_HEIGHT: f32 = 28.0;
+// Size of the box a collapsed frame is drawn as
+const FRAME_SUMMARY_SIZE: egui::Vec2 = egui::vec2(220.0, 64.0);
 
 pub struct MindMapApp {
     map: MindMap,                       // the mind map data
+    history: History,                   // undo/redo stacks for map edits
 
     // Interaction state
     dragging_node: Option<Uuid>,        // currently dragged node
+    drag_origin: Option<[f32; 2]>,      // position of the dragged node when the drag started
+    drag_offset: egui::Vec2,            // pointer movement since the drag started, in canvas units
+    alignment_guides: Vec<[Pos2; 2]>,   // lines showing what the dragged node is lined up with
+    dragging_frame: Option<Uuid>,       // currently dragged frame
+    frame_drag_origins: Vec<(Uuid, [f32; 2])>, // positions of the dragged frame's nodes when the drag started
     connecting_from: Option<Uuid>,      // node from which a connection is being made
     selected_nodes: Vec<Uuid>,          // currently selected nodes
     selected_edges: Vec<Uuid>,          // currently selected edges
@@ -46,19 +91,36 @@ pub struct MindMapApp {
     edit_annotation_id: Option<Uuid>,   // annotation currently being edited
     edit_annotation: EditableAnnotation,// editable annotation fields
     annotations_node_id: Option<Uuid>,  // node whose annotations are being viewed/edited
+    annotations_frame_id: Option<Uuid>, // frame whose annotations are being viewed/edited
 
     // Edge creation state
     pending_edge_from: Option<Uuid>,    // node where edge creation started
     pending_edge_to: Option<Uuid>,      // node where edge creation ends
     show_edge_type_menu: bool,          // whether to show edge type selection menu
-    selected_edge_type: EdgeType,       // selected edge type for new edge
+    selected_edge_type: Uuid,           // selected edge type for new edge
     edge_type_menu_pos: Option<Pos2>,   // position to show edge type menu
 
+    // Edge type definitions
+    show_edge_types_dialog: bool,       // whether to show the edge types dialog
+    edit_edge_types: Vec<EdgeType>,     // edge types being edited, saved as one undo step
+
     // Right-click context for edges
     rightclick_edge: Option<Uuid>,      // Edge that was right-clicked
     show_edge_context_menu: bool,       // Whether to show the edge context menu
     edge_context_menu_pos: Pos2,        // Position to show the edge context menu
 
+    // Right-click context for frames
+    rightclick_frame: Option<Uuid>,     // frame that was right-clicked
+    show_frame_context_menu: bool,      // whether to show the frame context menu
+    frame_context_menu_pos: Pos2,       // position to show the frame context menu
+
+    // Frame editing state
+    show_frame_dialog: bool,            // whether to show the frame dialog
+    frame_dialog_id: Option<Uuid>,      // frame being edited, None while creating one
+    frame_dialog_nodes: Vec<Uuid>,      // nodes a frame being created will contain
+    edit_frame_label: String,           // label being edited
+    edit_frame_color: egui::Color32,    // color being edited
+
     // Project creation prompt state
     show_create_project_prompt: bool,   // Whether to show the prompt
     pending_pdf_path: Option<String>,   // PDF path to add after project creation
@@ -72,6 +134,10 @@ pub struct MindMapApp {
     edge_color_picker_id: Option<Uuid>, // Edge ID for which color picker is shown
     selected_edge_color: egui::Color32, // Currently selected edge color
 
+    show_edge_label_dialog: bool,       // Whether to show the edge label dialog
+    edge_label_id: Option<Uuid>,        // Edge whose label is being edited
+    edit_edge_label: String,            // Label text being edited
+
     // Annotation state
     show_tags_panel: bool,              // whether to show tags panel
     show_add_tag_dialog: bool,         // whether to show add tag dialog
@@ -80,7 +146,42 @@ pub struct MindMapApp {
     edit_tag: EditableTag,              // editable tag fields
     tags_node_id: Option<Uuid>,          // node whose tags are being viewed/edited
 
+    // Full-text search state
+    show_search_panel: bool,            // whether to show the search panel
+    search_query: String,               // current search query
+    search_results: Vec<SearchHit>,     // hits for the current query
+    search_index: Option<SearchIndex>,  // full-text index of the project's PDFs
+    indexer: Option<(String, std::sync::mpsc::Receiver<anyhow::Result<SearchIndex>>)>, // pending background indexing and its project
+    canvas_rect: egui::Rect,            // screen rect of the canvas, used to center on nodes
+
+    notice: Option<(String, String)>,   // (title, message) shown in a dismissable window
+
+    layout: Option<ForceLayout>,        // force-directed layout being animated
+
+    // Citation linking state
+    citation_scans: Vec<CitationScan>,  // bibliographies being read in the background
+    link_proposals: Vec<(LinkProposal, bool)>, // References edges awaiting review, and whether each is ticked
 
+    show_duplicates: bool,              // whether to show the duplicates window
+    duplicate_groups: Vec<(DuplicateGroup, Uuid)>, // groups from the last scan, with the node each keeps
+
+    // Graph analytics state
+    show_analytics_panel: bool,         // whether to show the analytics panel
+    analytics: Option<Analytics>,       // metrics of the current map, kept up to date while used
+    analytics_for: Option<(u64, Option<Metric>)>, // history generation and size metric the metrics were computed for
+    size_metric: Option<Metric>,        // metric that node sizes are scaled by
+    node_scale: std::collections::HashMap<Uuid, f32>, // size factor per node from size_metric
+    highlighted_path: Vec<Uuid>,        // edges of the shortest path shown on the canvas
+    path_directed: bool,                // whether path finding follows edge direction
+
+    edge_routing: EdgeRouting,          // how edges are drawn between their nodes
+
+    show_minimap: bool,                 // whether to show the minimap
+    minimap_bounds: Option<egui::Rect>, // map area shown by the minimap, fixed while dragging on it
+
+    spatial_index: SpatialIndex,        // where nodes and edges are, for hit testing and drawing
+    node_layouts: std::collections::HashMap<Uuid, NodeLayout>, // laid out text of nodes drawn recently
+    font_atlas_fill: f32,               // fill ratio of the font atlas after the last frame's nodes were laid out
 }
 
 // Helper struct for editing metadata
@@ -90,6 +191,8 @@ struct EditableMetadata {
     authors: String,
     keywords: String,
     date: String,
+    doi: String,
+    arxiv: String,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -99,6 +202,183 @@ struct EditableTag {
     color: egui::Color32,
 }
 
+/// How edges are drawn between their nodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EdgeRouting {
+    Straight,   // direct lines, bent only to keep parallel edges apart
+    Curved,     // arcs
+    Orthogonal, // horizontal and vertical segments
+}
+
+impl EdgeRouting {
+    const ALL: [EdgeRouting; 3] = [EdgeRouting::Straight, EdgeRouting::Curved, EdgeRouting::Orthogonal];
+
+    fn name(self) -> &'static str {
+        match self {
+            EdgeRouting::Straight => "Straight",
+            EdgeRouting::Curved => "Curved",
+            EdgeRouting::Orthogonal => "Orthogonal",
+        }
+    }
+}
+
+// Node rects and edge routes in canvas coordinates, with grids to find them by
+// position. `sync_spatial_index` keeps it in step with the map.
+struct SpatialIndex {
+    nodes: std::collections::HashMap<Uuid, IndexedNode>,
+    edges: std::collections::HashMap<Uuid, IndexedEdge>,
+    node_grid: Grid,
+    edge_grid: Grid,
+    routing: EdgeRouting, // routing the edges were laid out with
+    frames: std::collections::HashMap<Uuid, egui::Rect>, // box each frame is drawn as
+    covered: std::collections::HashMap<Uuid, (Uuid, egui::Rect)>, // nodes hidden in a collapsed frame -> (frame, its box)
+    synced: Option<u64>,                    // history generation the whole map was last checked at
+    stale: std::collections::HashSet<Uuid>, // nodes changed outside the history since then
+}
+
+impl SpatialIndex {
+    fn new(routing: EdgeRouting) -> SpatialIndex {
+        SpatialIndex {
+            nodes: std::collections::HashMap::new(),
+            edges: std::collections::HashMap::new(),
+            node_grid: Grid::new(GRID_CELL_SIZE),
+            edge_grid: Grid::new(GRID_CELL_SIZE),
+            routing,
+            frames: std::collections::HashMap::new(),
+            covered: std::collections::HashMap::new(),
+            synced: None,
+            stale: std::collections::HashSet::new(),
+        }
+    }
+}
+
+struct IndexedNode {
+    order: usize,      // position in the map's node list; earlier nodes win hit tests
+    center: Pos2,
+    rect: egui::Rect,
+    shape: NodeShape,  // what `rect` was measured for
+}
+
+// Everything besides its position that the size of a node depends on
+#[derive(Clone, Copy, PartialEq)]
+struct NodeShape {
+    fingerprint: u64, // hash of the title, collapsed state and metadata
+    scale: f32,
+}
+
+impl NodeShape {
+    fn of(node: &Node, scale: f32) -> NodeShape {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (&node.title, node.collapsed, &node.metadata).hash(&mut hasher);
+        NodeShape { fingerprint: hasher.finish(), scale }
+    }
+}
+
+// Text of a node laid out for drawing at one zoom level
+struct NodeLayout {
+    zoom: f32,         // canvas zoom, without the node's own scale
+    shape: NodeShape,  // what the text was laid out for
+    size: egui::Vec2,  // size of the node on screen
+    title: std::sync::Arc<egui::Galley>,
+    metadata: Vec<(std::sync::Arc<egui::Galley>, std::sync::Arc<egui::Galley>, f32)>, // (label, value, label width) of expanded nodes
+}
+
+impl NodeLayout {
+    fn new(ctx: &egui::Context, node: &Node, canvas_zoom: f32, shape: NodeShape) -> NodeLayout {
+        let zoom = canvas_zoom * shape.scale;
+        let font_id = egui::FontId::proportional(14.0 * zoom);
+        let bold_font_id = egui::FontId::monospace(14.0 * zoom);
+
+        let padding = egui::vec2(16.0, 12.0) * zoom;
+        let title = ctx.fonts_mut(|f| f.layout_no_wrap(node.title.clone(), font_id.clone(), egui::Color32::BLACK));
+
+        // Calculate node size based on collapse state
+        let mut size = if node.collapsed {
+            // When collapsed, size based on title
+            let text_size = title.size();
+            egui::vec2(text_size.x + padding.x * 2.0, text_size.y + padding.y)
+        } else {
+            // When expanded, use minimum width or title width, whichever is larger
+            let title_width = title.size().x + padding.x * 2.0;
+            let min_width = 300.0 * zoom;
+            let node_width = title_width.max(min_width);
+            egui::vec2(node_width, 0.0) // Height will be calculated below
+        };
+
+        let mut metadata = Vec::new();
+        if !node.collapsed {
+            if let Some(node_metadata) = &node.metadata {
+                let max_width = size.x - padding.x * 2.0;
+
+                // Create layout for each metadata field
+                let authors_str = node_metadata.authors.join(", ");
+                let keywords_str = node_metadata.keywords.join(", ");
+                let mut fields = vec![
+                    ("Title: ", &node_metadata.title),
+                    ("Authors: ", &authors_str),
+                    ("Keywords: ", &keywords_str),
+                    ("Date: ", &node_metadata.date),
+                ];
+                if let Some(doi) = &node_metadata.doi {
+                    fields.push(("DOI: ", doi));
+                }
+                if let Some(arxiv) = &node_metadata.arxiv {
+                    fields.push(("arXiv: ", arxiv));
+                }
+
+                // Find the widest label to align all values
+                let label_width = MindMapApp::find_widest_label(ctx, fields.clone(), zoom);
+
+                for (label, value) in fields {
+                    // Create label galley (bold)
+                    let label_galley = ctx.fonts_mut(|f|
+                        f.layout_no_wrap(label.to_string(), bold_font_id.clone(), egui::Color32::BLACK)
+                    );
+
+                    // Create wrapped value galley with remaining width
+                    let value_galley = ctx.fonts_mut(|f|
+                        f.layout(
+                            value.to_string(),
+                            font_id.clone(),
+                            egui::Color32::BLACK,
+                            max_width - label_width
+                        )
+                    );
+
+                    metadata.push((label_galley, value_galley, label_width));
+                }
+
+                // Calculate total height needed
+                let line_spacing = 4.0 * zoom;
+                size.y = padding.y;
+                for (_, value_galley, _) in &metadata {
+                    size.y += value_galley.size().y + line_spacing;
+                }
+                size.y += padding.y;
+            } else {
+                // No metadata, just show title with calculated width
+                size.y = title.size().y + padding.y;
+            }
+        }
+
+        NodeLayout { zoom: canvas_zoom, shape, size, title, metadata }
+    }
+}
+
+struct IndexedEdge {
+    from: Uuid,
+    to: Uuid,
+    points: Vec<Pos2>, // the drawn line, empty when a node is missing
+}
+
+// Reference list of a PDF node being read in the background
+struct CitationScan {
+    node_id: Uuid,   // the citing node
+    requested: bool, // started from the context menu rather than by adding the PDF
+    receiver: std::sync::mpsc::Receiver<anyhow::Result<Vec<String>>>,
+}
+
 // Annotation types
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 #[derive(PartialEq)]
@@ -177,15 +457,33 @@ impl MindMapApp {
                 egui::Sense::click_and_drag(),
             );
             let rect = response.rect;
+            self.canvas_rect = rect;
+
+            // The minimap is laid over the canvas and takes the clicks on it
+            let minimap = self.show_minimap.then(|| {
+                let minimap_rect = egui::Rect::from_min_size(rect.right_bottom() - MINIMAP_SIZE - egui::vec2(MINIMAP_MARGIN, MINIMAP_MARGIN), MINIMAP_SIZE);
+                ui.interact(minimap_rect, ui.id().with("minimap"), egui::Sense::click_and_drag())
+            });
+            let over_minimap = minimap.as_ref().is_some_and(|m| m.hovered() || m.dragged());
 
-            // --- Handle panning with middle mouse ---
-            self.handle_navigation(ctx, &response, rect);
+            if !over_minimap {
+                // --- Handle panning with middle mouse ---
+                self.handle_navigation(ctx, &response, rect);
+            }
 
             // --- Handle keyboard events ---
             self.handle_keyboard_events(ctx);
 
-            // --- Handle mouse events ---
-            self.handle_mouse_events(ctx, &response, rect);
+            if !over_minimap {
+                // --- Handle mouse events ---
+                self.sync_spatial_index(ctx);
+                self.handle_mouse_events(ctx, &response, rect);
+            }
+
+            // --- Draw grid and frames behind everything else ---
+            self.sync_spatial_index(ctx); // nodes may have moved while handling input
+            self.draw_grid(rect, &painter);
+            self.draw_frames(rect, &painter);
 
             // --- Draw edges ---
             self.draw_edges(rect, &painter);
@@ -198,9 +496,15 @@ impl MindMapApp {
 
             // --- Draw nodes ---
             self.draw_nodes(rect, &painter, ctx);
+            self.draw_alignment_guides(rect, &painter);
 
             // --- Draw marquee rectangle ---
             self.draw_marquee_rect(&painter);
+
+            // --- Draw minimap ---
+            if let Some(minimap) = &minimap {
+                self.draw_minimap(&painter, minimap, rect);
+            }
         });
     }
 
@@ -211,14 +515,12 @@ impl MindMapApp {
 
         // --- Handle zoom with scroll wheel ---
         let zoom_delta = ctx.input(|i| i.zoom_delta());
-        if (zoom_delta - 1.0).abs() > f32::EPSILON {
-            if let Some(pointer_pos) = ctx.input(|i| i.pointer.hover_pos()) {
-                // Zoom relative to cursor
-                let canvas_pos = (pointer_pos - rect.min.to_vec2() - self.pan) / self.zoom;
-                self.zoom *= zoom_delta.clamp(0.1, 5.0);
-                // Adjust pan so zoom centers around cursor
-                self.pan = pointer_pos - rect.min.to_vec2() - canvas_pos * self.zoom;
-            }
+        if (zoom_delta - 1.0).abs() > f32::EPSILON && let Some(pointer_pos) = ctx.input(|i| i.pointer.hover_pos()) {
+            // Zoom relative to cursor
+            let canvas_pos = (pointer_pos - rect.min.to_vec2() - self.pan) / self.zoom;
+            self.zoom *= zoom_delta.clamp(0.1, 5.0);
+            // Adjust pan so zoom centers around cursor
+            self.pan = pointer_pos - rect.min.to_vec2() - canvas_pos * self.zoom;
         }
     }
 
@@ -232,6 +534,26 @@ impl MindMapApp {
 
         // manual save
         self.handle_save(ctx);
+
+        // undo / redo
+        self.handle_undo_redo(ctx);
+
+        // open full-text search
+        if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::F)) {
+            self.show_search_panel = true;
+        }
+    }
+
+    fn handle_undo_redo(&mut self, ctx: &egui::Context) {
+        // Let text fields handle their own undo
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        if ctx.input(|i| i.modifiers.ctrl && i.modifiers.shift && i.key_pressed(egui::Key::Z)) {
+            self.redo();
+        } else if ctx.input(|i| i.modifiers.ctrl && i.key_pressed(egui::Key::Z)) {
+            self.undo();
+        }
     }
 
     fn handle_save(&mut self, ctx: &egui::Context) {
@@ -254,16 +576,32 @@ impl MindMapApp {
 
     fn handle_delete(&mut self, ctx: &egui::Context) {
         if ctx.input(|i| i.key_pressed(egui::Key::Delete)) {
+            // Commands are built against the map as it is after the previous
+            // removal, so the whole delete can be undone as one step
+            let mut commands = Vec::new();
+
+            // Remove selected edges
+            for edge_id in &self.selected_edges {
+                if let Some(command) = Command::remove_edge(&self.map, *edge_id) {
+                    command.apply(&mut self.map);
+                    commands.push(command);
+                }
+            }
+            self.selected_edges.clear();
+
             // Remove selected nodes
             for node_id in &self.selected_nodes {
-                self.map.remove_node(*node_id);
+                if let Some(command) = Command::remove_node(&self.map, *node_id) {
+                    command.apply(&mut self.map);
+                    commands.push(command);
+                }
             }
             self.selected_nodes.clear();
 
-            // Remove selected edges
-            self.map.edges.retain(|e| !self.selected_edges.contains(&e.id));
-            self.selected_edges.clear();
-            self.dirty = true;
+            if !commands.is_empty() {
+                self.history.record(Command::Batch(commands));
+                self.dirty = true;
+            }
         }
     }
 
@@ -273,69 +611,61 @@ impl MindMapApp {
                 (pointer_pos - rect.min.to_vec2() - self.pan) / self.zoom;
 
             // Left click selection / creation
-            self.handle_left_click(ctx, &response, rect, pointer_pos, canvas_pos);
+            self.handle_left_click(ctx, response, rect, pointer_pos, canvas_pos);
 
             // Drag existing node with left mouse
-            self.handle_left_drag(ctx, &response, canvas_pos);
+            self.handle_left_drag(ctx, response, canvas_pos);
 
             // Right click handling - Updated to show context menu
-            self.handle_right_click(ctx, &response, pointer_pos, canvas_pos);
+            self.handle_right_click(response, pointer_pos, canvas_pos);
 
             // --- Right button: create connections ---
-            self.handle_right_drag(ctx, &response, canvas_pos);
+            self.handle_right_drag(ctx, response, canvas_pos);
         }
     }
 
     fn handle_right_drag(&mut self, ctx: &egui::Context, response: &egui::Response, canvas_pos: Pos2){
         if response.drag_started_by(egui::PointerButton::Secondary) {
             // start connection from node under cursor
-            self.start_edge(ctx, canvas_pos)
+            self.start_edge(canvas_pos)
         }
 
-        if response.drag_stopped_by(egui::PointerButton::Secondary) {
-            if let Some(start_id) = self.connecting_from.take() {
-                self.stop_edge(ctx, canvas_pos, start_id);
-            }
+        if response.drag_stopped_by(egui::PointerButton::Secondary) && let Some(start_id) = self.connecting_from.take() {
+            self.stop_edge(ctx, canvas_pos, start_id);
         }
     }
 
-    fn handle_right_click(&mut self, ctx: &egui::Context, response: &egui::Response, pointer_pos: Pos2, canvas_pos: Pos2) {
+    fn handle_right_click(&mut self, response: &egui::Response, pointer_pos: Pos2, canvas_pos: Pos2) {
         if response.clicked_by(egui::PointerButton::Secondary) {
             let mut clicked_any = false;
 
             // Check if clicked on a node
-            for node in &self.map.nodes {
-                let node_rect = get_node_rect(ctx, node, self.zoom);
-                if node_rect.contains(canvas_pos) {
-                    // Show context menu for this node
-                    self.rightclick_node = Some(node.id);
-                    self.context_menu_pos = pointer_pos;
-                    self.show_node_context_menu = true;
+            if let Some(node_id) = self.node_at(canvas_pos) {
+                // Show context menu for this node
+                self.rightclick_node = Some(node_id);
+                self.context_menu_pos = pointer_pos;
+                self.show_node_context_menu = true;
+                clicked_any = true;
+            }
+
+            if !clicked_any {
+                // Check if clicked on a frame's label or collapsed box
+                if let Some(frame_id) = self.frame_at(canvas_pos) {
+                    self.rightclick_frame = Some(frame_id);
+                    self.frame_context_menu_pos = pointer_pos;
+                    self.show_frame_context_menu = true;
                     clicked_any = true;
-                    break;
                 }
             }
 
             if !clicked_any {
                 // Check if clicked on an edge
-                for edge in &self.map.edges {
-                    let from = self.map.nodes.iter().find(|n| n.id == edge.from);
-                    let to = self.map.nodes.iter().find(|n| n.id == edge.to);
-                    if let (Some(f), Some(t)) = (from, to) {
-                        let dist = point_line_distance(
-                            egui::pos2(f.x, f.y),
-                            egui::pos2(t.x, t.y),
-                            canvas_pos,
-                        );
-                        if dist < 8.0 {
-                            // Right-clicked on an edge
-                            self.rightclick_edge = Some(edge.id);
-                            self.edge_context_menu_pos = pointer_pos;
-                            self.show_edge_context_menu = true;
-                            clicked_any = true;
-                            break;
-                        }
-                    }
+                if let Some(edge_id) = self.edge_at(canvas_pos) {
+                    // Right-clicked on an edge
+                    self.rightclick_edge = Some(edge_id);
+                    self.edge_context_menu_pos = pointer_pos;
+                    self.show_edge_context_menu = true;
+                    clicked_any = true;
                 }
             }
 
@@ -343,6 +673,7 @@ impl MindMapApp {
             if !clicked_any {
                 self.show_node_context_menu = false;
                 self.show_edge_context_menu = false;
+                self.show_frame_context_menu = false;
             }
         }
     }
@@ -352,27 +683,60 @@ impl MindMapApp {
             if ctx.input(|i| i.modifiers.ctrl) {
                 // Preserve the connecting_from state during drag
                 if self.connecting_from.is_none() {
-                    self.start_edge(ctx, canvas_pos);
+                    self.start_edge(canvas_pos);
                 }
             }else {
                 let pointer_pos = response.interact_pointer_pos().unwrap();
 
-                if let Some(id) = self.dragging_node {
+                // Holding Alt moves freely, without snapping
+                let snap = !ctx.input(|i| i.modifiers.alt);
+                if let (Some(id), Some(origin)) = (self.dragging_node, self.drag_origin) {
+                    self.drag_offset += response.drag_delta() / self.zoom;
+                    let position = egui::pos2(origin[0], origin[1]) + self.drag_offset;
+                    let position = if snap {
+                        self.snap_position(id, position)
+                    } else {
+                        self.alignment_guides.clear();
+                        position
+                    };
                     if let Some(node) = self.map.nodes.iter_mut().find(|n| n.id == id) {
-                        node.x += response.drag_delta().x / self.zoom;
-                        node.y += response.drag_delta().y / self.zoom;
+                        node.x = position.x;
+                        node.y = position.y;
+                        self.spatial_index.stale.insert(id);
                         self.dirty = true;
                     }
-                } else {
-                    for node in &self.map.nodes {
-                        let rect = get_node_rect(ctx, node, self.zoom);
-                        if rect.contains(canvas_pos) && self.marquee_rect == None {
-                            self.dragging_node = Some(node.id);
-                            break;
+                } else if self.dragging_frame.is_some() {
+                    // Members move together with their frame, by whole grid cells when snapping
+                    self.drag_offset += response.drag_delta() / self.zoom;
+                    let grid = self.map.grid;
+                    let offset = if snap && grid.snap {
+                        egui::vec2(snap_to_grid(self.drag_offset.x, grid.size), snap_to_grid(self.drag_offset.y, grid.size))
+                    } else {
+                        self.drag_offset
+                    };
+                    for (id, origin) in &self.frame_drag_origins {
+                        if let Some(node) = self.map.nodes.iter_mut().find(|n| n.id == *id) {
+                            node.x = origin[0] + offset.x;
+                            node.y = origin[1] + offset.y;
+                            self.spatial_index.stale.insert(*id);
                         }
                     }
+                    self.dirty = true;
+                } else if self.marquee_rect.is_none() {
+                    let grabbed = self.node_at(canvas_pos).and_then(|id| self.map.nodes.iter().find(|n| n.id == id));
+                    self.drag_offset = egui::Vec2::ZERO;
+                    if let Some(node) = grabbed {
+                        self.dragging_node = Some(node.id);
+                        self.drag_origin = Some([node.x, node.y]);
+                    } else if let Some(frame) = self.frame_at(canvas_pos).and_then(|id| self.map.frames.iter().find(|f| f.id == id)) {
+                        self.dragging_frame = Some(frame.id);
+                        self.frame_drag_origins = self.map.nodes.iter()
+                            .filter(|n| frame.nodes.contains(&n.id))
+                            .map(|n| (n.id, [n.x, n.y]))
+                            .collect();
+                    }
                 }
-                if self.dragging_node.is_none() {
+                if self.dragging_node.is_none() && self.dragging_frame.is_none() {
                     // Start or continue marquee
                     if self.marquee_start.is_none() {
                         self.marquee_start = Some(pointer_pos);
@@ -395,35 +759,49 @@ impl MindMapApp {
                 let rect_max = (rect.max - response.rect.min.to_vec2() - self.pan) / self.zoom;
                 let selection_rect = egui::Rect::from_two_pos(rect_min, rect_max);
 
-                // Select all nodes inside marquee
-                self.selected_nodes = Vec::new(); // clear single selection
-                self.selected_edges = Vec::new(); // clear edge selection
-                for node in &self.map.nodes {
-                    let node_pos = egui::pos2(node.x, node.y);
-                    if selection_rect.contains(node_pos) {
-                        // You can keep a Vec<Uuid> for multiple selection
-                        // For simplicity here, we just mark the last node selected
-                        self.selected_nodes.push(node.id);
-                    }
-                }
+                // Select all nodes inside marquee, replacing the selection
+                let index = &self.spatial_index;
+                let center = |id: &Uuid| index.nodes.get(id).map(|n| (n.order, n.center));
+                let mut nodes: Vec<(usize, Uuid)> = index.node_grid.query(bounds(selection_rect))
+                    .into_iter()
+                    .filter_map(|id| {
+                        let (order, center) = center(&id)?;
+                        (selection_rect.contains(center) && !index.covered.contains_key(&id)).then_some((order, id))
+                    })
+                    .collect();
+                nodes.sort();
+                self.selected_nodes = nodes.into_iter().map(|(_, id)| id).collect();
 
                 // Select edges where both endpoints are inside rect
-                for edge in &self.map.edges {
-                    let from = self.map.nodes.iter().find(|n| n.id == edge.from);
-                    let to = self.map.nodes.iter().find(|n| n.id == edge.to);
-                    if let (Some(f), Some(t)) = (from, to) {
-                        let p1 = egui::pos2(f.x, f.y);
-                        let p2 = egui::pos2(t.x, t.y);
-                        if selection_rect.contains(p1) && selection_rect.contains(p2) {
-                            self.selected_edges.push(edge.id);
-                        }
-                    }
-                }
+                let inside = |id: &Uuid| center(id).is_some_and(|(_, c)| selection_rect.contains(c));
+                let candidates = index.edge_grid.query(bounds(selection_rect));
+                self.selected_edges = self.map.edges.iter()
+                    .filter(|e| candidates.contains(&e.id) && inside(&e.from) && inside(&e.to))
+                    .map(|e| e.id)
+                    .collect();
 
                 self.marquee_start = None;
             }
 
-            self.dragging_node = None;
+            // Record the whole drag as a single undo step
+            self.alignment_guides.clear();
+            if let (Some(id), Some(origin)) = (self.dragging_node.take(), self.drag_origin.take())
+                && let Some(node) = self.map.nodes.iter().find(|n| n.id == id)
+                && origin != [node.x, node.y]
+            {
+                self.history.record(Command::MoveNodes(vec![(id, origin, [node.x, node.y])]));
+            }
+            if self.dragging_frame.take().is_some() {
+                let moves: Vec<(Uuid, [f32; 2], [f32; 2])> = std::mem::take(&mut self.frame_drag_origins).into_iter()
+                    .filter_map(|(id, origin)| {
+                        let node = self.map.nodes.iter().find(|n| n.id == id)?;
+                        (origin != [node.x, node.y]).then_some((id, origin, [node.x, node.y]))
+                    })
+                    .collect();
+                if !moves.is_empty() {
+                    self.history.record(Command::MoveNodes(moves));
+                }
+            }
         }
     }
 
@@ -439,82 +817,62 @@ impl MindMapApp {
                 let mut clicked_node = false;
 
                 // Check if double-clicked on a node
-                for node in &self.map.nodes {
-                    let node_rect = get_node_rect(ctx, node, self.zoom);
-
-                    if node_rect.contains(canvas_pos) {
-                        if let Some(file_path) = &node.path {
-                            if let Some(project_dir) = &self.current_file {
-                                let full_path = std::path::Path::new(project_dir).join(file_path);
-                                if let Err(e) = opener::open(full_path.to_str().unwrap()) {
-                                    eprintln!("Failed to open PDF: {}", e);
-                                }
-                            } else {
-                                eprintln!("No project directory set; cannot open PDF.");
+                if let Some(node) = self.node_at(canvas_pos).and_then(|id| self.map.nodes.iter().find(|n| n.id == id)) {
+                    if let Some(file_path) = &node.path {
+                        if let Some(project_dir) = &self.current_file {
+                            let full_path = std::path::Path::new(project_dir).join(file_path);
+                            if let Err(e) = opener::open(full_path.to_str().unwrap()) {
+                                eprintln!("Failed to open PDF: {}", e);
                             }
+                        } else {
+                            eprintln!("No project directory set; cannot open PDF.");
                         }
+                    }
+                    clicked_node = true;
+                }
+
+                if !clicked_node {
+                    // Double-clicked on a frame's label: collapse or expand it
+                    if let Some(frame_id) = self.frame_at(canvas_pos) {
+                        self.update_frame(frame_id, |frame| frame.collapsed = !frame.collapsed);
                         clicked_node = true;
-                        break;
                     }
                 }
 
                 if !clicked_node {
                     // Double-clicked on empty space: create new node
-                    self.map.add_node("New".into(), canvas_pos.x, canvas_pos.y);
-                    self.dirty = true;
+                    let id = self.map.add_node("New".into(), canvas_pos.x, canvas_pos.y);
+                    self.record_added_node(id);
                 }
             }
 
             if ctx.input(|i| i.modifiers.ctrl) {
                 let canvas_pos = (response.interact_pointer_pos().unwrap() - rect.min.to_vec2() - self.pan) / self.zoom;
+                let hit = self.nodes_at(canvas_pos);
                 for node in &mut self.map.nodes{
-                    let node_rect = get_node_rect(ctx, node, self.zoom);
-                    if node_rect.contains(canvas_pos) {
-                        if ctx.input(|i| i.modifiers.ctrl) {
-                            if node.collapsed{
-                                node.collapsed = false;
-                            } else {
-                                node.collapsed = true;
-                            }
-                        }
+                    if hit.contains(&node.id) && ctx.input(|i| i.modifiers.ctrl) {
+                        node.collapsed = !node.collapsed;
+                        self.spatial_index.stale.insert(node.id);
                     }
                 }
             }
             else if ctx.input(|i| i.modifiers.shift) {
                 let mut clicked_any: bool = false;
-                for node in &self.map.nodes {
-                    let node_rect = get_node_rect(ctx, node, self.zoom);
-                    if node_rect.contains(canvas_pos) {
-                        if self.selected_nodes.contains(&node.id){
-                            self.selected_nodes.retain(|n| *n != node.id)
-                        } else {
-                            self.selected_nodes.push(node.id);
-                        }
-                        clicked_any = true;
-                        break;
+                if let Some(node_id) = self.node_at(canvas_pos) {
+                    if self.selected_nodes.contains(&node_id){
+                        self.selected_nodes.retain(|n| *n != node_id)
+                    } else {
+                        self.selected_nodes.push(node_id);
                     }
+                    clicked_any = true;
                 }
-                if !clicked_any {
-                    for edge in &self.map.edges {
-                        let from = self.map.nodes.iter().find(|n| n.id == edge.from);
-                        let to = self.map.nodes.iter().find(|n| n.id == edge.to);
-                        if let (Some(f), Some(t)) = (from, to) {
-                            let dist = point_line_distance(
-                                egui::pos2(f.x, f.y),
-                                egui::pos2(t.x, t.y),
-                                canvas_pos,
-                            );
-                            if dist < 8.0 {
-                                if self.selected_edges.contains(&edge.id){
-                                    self.selected_edges.retain(|n| *n != edge.id)
-                                } else {
-                                    self.selected_edges.push(edge.id);
-                                }
-                                clicked_any = true;
-                                break;
-                            }
-                        }
+                if !clicked_any && let Some(edge_id) = self.edge_at(canvas_pos) {
+                    if self.selected_edges.contains(&edge_id){
+                        self.selected_edges.retain(|n| *n != edge_id)
+                    } else {
+                        self.selected_edges.push(edge_id);
                     }
+                    clicked_any = true;
                 }
                 if !clicked_any {
                     // Shift + left click → open PDF file picker
@@ -532,8 +890,14 @@ impl MindMapApp {
                             let file_name = path.file_name().unwrap().to_str().unwrap();
                             let dest_path = pdfs_dir.join(file_name);
                             std::fs::copy(&path, &dest_path).expect("failed to copy pdf");
-                            self.map.add_pdf_node(&format!("{}/{}",pdfs_dir.to_str().unwrap(), file_name), canvas_pos.x, canvas_pos.y).expect("failed to add pdf node");
-                            self.dirty = true;
+                            match self.map.add_pdf_node(&format!("{}/{}",pdfs_dir.to_str().unwrap(), file_name), canvas_pos.x, canvas_pos.y) {
+                                Ok(id) => {
+                                    self.record_added_node(id);
+                                    self.start_indexing();
+                                    self.scan_citations(id, false);
+                                }
+                                Err(e) => eprintln!("Failed to add PDF node: {}", e),
+                            }
                         } else {
                             // Handle case where no project is saved yet
                             self.pending_pdf_path = Some(path_str);
@@ -543,37 +907,19 @@ impl MindMapApp {
                 }
             }else{
                 // Check if clicked on a node
-                for node in &self.map.nodes {
-                    let node_rect = get_node_rect(ctx, node, self.zoom);
-                    if node_rect.contains(canvas_pos) {
-                        self.selected_nodes= Vec::new();
-                        self.selected_nodes.push(node.id);
-                        self.selected_edges = Vec::new();
-                        clicked_any = true;
-                        break;
-                    }
+                if let Some(node_id) = self.node_at(canvas_pos) {
+                    self.selected_nodes= Vec::new();
+                    self.selected_nodes.push(node_id);
+                    self.selected_edges = Vec::new();
+                    clicked_any = true;
                 }
 
                 // Check if clicked on an edge (line proximity)
-                if !clicked_any {
-                    for edge in &self.map.edges {
-                        let from = self.map.nodes.iter().find(|n| n.id == edge.from);
-                        let to = self.map.nodes.iter().find(|n| n.id == edge.to);
-                        if let (Some(f), Some(t)) = (from, to) {
-                            let dist = point_line_distance(
-                                egui::pos2(f.x, f.y),
-                                egui::pos2(t.x, t.y),
-                                canvas_pos,
-                            );
-                            if dist < 8.0 {
-                                self.selected_edges = Vec::new();
-                                self.selected_edges.push(edge.id);
-                                self.selected_nodes = Vec::new();
-                                clicked_any = true;
-                                break;
-                            }
-                        }
-                    }
+                if !clicked_any && let Some(edge_id) = self.edge_at(canvas_pos) {
+                    self.selected_edges = Vec::new();
+                    self.selected_edges.push(edge_id);
+                    self.selected_nodes = Vec::new();
+                    clicked_any = true;
                 }
 
                 // Clicked on empty space: deselect
@@ -585,149 +931,468 @@ impl MindMapApp {
         }
     }
 
+    fn draw_grid(&self, rect: egui::Rect, painter: &egui::Painter) {
+        if !self.map.grid.visible || self.map.grid.size <= 0.0 {
+            return;
+        }
+        // When zoomed out, only every second, fourth, ... line is drawn
+        let mut step = self.map.grid.size;
+        while step * self.zoom < GRID_MIN_SPACING {
+            step *= 2.0;
+        }
+        let visible = self.visible_canvas_rect(rect);
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgb(58, 59, 72));
+        let to_screen = |pos: Pos2| pos * self.zoom + self.pan + rect.min.to_vec2();
+
+        let mut x = (visible.min.x / step).floor() * step;
+        while x <= visible.max.x {
+            let screen_x = to_screen(egui::pos2(x, 0.0)).x;
+            painter.line_segment([egui::pos2(screen_x, rect.top()), egui::pos2(screen_x, rect.bottom())], stroke);
+            x += step;
+        }
+        let mut y = (visible.min.y / step).floor() * step;
+        while y <= visible.max.y {
+            let screen_y = to_screen(egui::pos2(0.0, y)).y;
+            painter.line_segment([egui::pos2(rect.left(), screen_y), egui::pos2(rect.right(), screen_y)], stroke);
+            y += step;
+        }
+    }
+
+    fn draw_alignment_guides(&self, rect: egui::Rect, painter: &egui::Painter) {
+        let stroke = egui::Stroke::new(1.0, egui::Color32::from_rgb(255, 80, 200));
+        for [a, b] in &self.alignment_guides {
+            let to_screen = |pos: Pos2| pos * self.zoom + self.pan + rect.min.to_vec2();
+            painter.add(egui::Shape::dashed_line(&[to_screen(*a), to_screen(*b)], stroke, 6.0, 4.0));
+        }
+    }
+
+    fn draw_frames(&mut self, rect: egui::Rect, painter: &egui::Painter) {
+        let visible = self.visible_canvas_rect(rect);
+        for frame in &self.map.frames {
+            let Some(frame_rect) = self.spatial_index.frames.get(&frame.id).filter(|r| r.intersects(visible)) else { continue };
+            let screen_rect = egui::Rect::from_min_max(
+                frame_rect.min * self.zoom + self.pan + rect.min.to_vec2(),
+                frame_rect.max * self.zoom + self.pan + rect.min.to_vec2(),
+            );
+            let color = color32(frame.color);
+            let font_id = egui::FontId::proportional(16.0 * self.zoom);
+
+            if frame.collapsed {
+                // One box standing in for all of the frame's nodes
+                painter.rect(screen_rect, 8.0 * self.zoom, color, egui::Stroke::new(2.0, egui::Color32::BLACK), egui::StrokeKind::Middle);
+                painter.text(screen_rect.center() - egui::vec2(0.0, 10.0) * self.zoom, egui::Align2::CENTER_CENTER, &frame.label, font_id, egui::Color32::BLACK);
+                let count = frame.nodes.len();
+                let summary = format!("{} node{}", count, if count == 1 { "" } else { "s" });
+                painter.text(screen_rect.center() + egui::vec2(0.0, 12.0) * self.zoom, egui::Align2::CENTER_CENTER, summary, egui::FontId::proportional(12.0 * self.zoom), egui::Color32::BLACK);
+            } else {
+                let fill = egui::Color32::from_rgba_unmultiplied(frame.color[0], frame.color[1], frame.color[2], 40);
+                painter.rect(screen_rect, 8.0 * self.zoom, fill, egui::Stroke::new(2.0, color), egui::StrokeKind::Middle);
+                let label_pos = screen_rect.left_top() + egui::vec2(FRAME_PADDING / 2.0, FRAME_LABEL_HEIGHT / 2.0) * self.zoom;
+                painter.text(label_pos, egui::Align2::LEFT_CENTER, &frame.label, font_id, color);
+            }
+
+            // Draw annotation icon if the frame has annotations
+            if !frame.annotations.is_empty() {
+                let icon_rect = egui::Rect::from_min_size(screen_rect.right_top() - egui::vec2(21.0, -5.0) * self.zoom, egui::vec2(16.0, 16.0) * self.zoom);
+                painter.rect(icon_rect, 5.0, egui::Color32::LIGHT_GRAY, egui::Stroke::new(1.0, egui::Color32::BLACK), egui::StrokeKind::Middle);
+                painter.text(icon_rect.center(), egui::Align2::CENTER_CENTER, "📝", egui::FontId::proportional(14.0 * self.zoom), egui::Color32::BLACK);
+            }
+        }
+    }
+
     fn draw_edges(&mut self, rect: egui::Rect, painter: &egui::Painter) {
-        for edge in &self.map.edges {
-            let from = self.map.nodes.iter().find(|n| n.id == edge.from);
-            let to = self.map.nodes.iter().find(|n| n.id == edge.to);
-            if let (Some(f), Some(t)) = (from, to) {
-                let p1 = egui::pos2(f.x, f.y) * self.zoom + self.pan + rect.min.to_vec2();
-                let p2 = egui::pos2(t.x, t.y) * self.zoom + self.pan + rect.min.to_vec2();
-                let fill = if let Some(edge_color) = edge.color {
-                    edge_color
+        // Only edges passing through the screen are drawn
+        let on_screen = self.spatial_index.edge_grid.query(bounds(self.visible_canvas_rect(rect)));
+        for edge in self.map.edges.iter().filter(|e| on_screen.contains(&e.id)) {
+            let Some(indexed) = self.spatial_index.edges.get(&edge.id) else { continue };
+            let points: Vec<Pos2> = indexed.points.iter()
+                .map(|p| *p * self.zoom + self.pan + rect.min.to_vec2())
+                .collect();
+            let edge_type = self.map.edge_type(edge.edge_type);
+            let mut fill = edge.color
+                .or(edge_type.map(|t| t.color))
+                .unwrap_or(egui::Color32::GRAY.to_array());
+            let mut width = 2.0;
+            if self.selected_edges.contains(&edge.id) {
+                width = 3.0;
+            }
+            // Emphasize the edges of a highlighted shortest path
+            if self.highlighted_path.contains(&edge.id) {
+                fill = egui::Color32::from_rgb(255, 140, 0).to_array();
+                width = 5.0;
+            }
+            let stroke = egui::Stroke::new(width, color32(fill));
+
+            // Draw line
+            draw_line(painter, &points, stroke, edge_type.map_or(DashStyle::Solid, |t| t.dash));
+
+            // Draw arrowhead for directed types, its tip touching the target node
+            let arrow = edge_type.filter(|t| t.directed).map_or(ArrowStyle::None, |t| t.arrow);
+            if let (true, [.., before_tip, tip]) = (arrow != ArrowStyle::None, points.as_slice()) {
+                let arrow_size = 15.0; // Size of the arrowhead
+                let angle = std::f32::consts::PI / 6.0; // 30 degrees
+                let dir = (*tip - *before_tip).normalized(); // Direction of the edge where it arrives
+                let perp = egui::Vec2::new(-dir.y, dir.x); // Perpendicular vector
+
+                // Calculate the arrowhead points
+                let base = *tip - dir * arrow_size; // Base of the arrowhead
+                let side1 = base + perp * arrow_size * angle.tan(); // One side of the arrowhead
+                let side2 = base - perp * arrow_size * angle.tan(); // Other side of the arrowhead
+
+                if arrow == ArrowStyle::Filled {
+                    painter.add(egui::Shape::convex_polygon(vec![*tip, side1, side2], stroke.color, stroke));
                 } else {
-                    egui::Color32::GRAY.to_array()
-                };
-                let mut width = 2.0;
-                if self.selected_edges.contains(&edge.id) {
-                    width = 3.0;
+                    painter.line_segment([*tip, side1], stroke); // Line from tip to one side
+                    painter.line_segment([*tip, side2], stroke); // Line from tip to other side
+                }
+            }
+
+            // Draw the label on a pill halfway along the edge
+            if let Some(label) = edge.label.as_deref().filter(|_| self.zoom >= LABEL_MIN_ZOOM) {
+                let galley = painter.layout_no_wrap(label.to_string(), egui::FontId::proportional(12.0 * self.zoom), egui::Color32::WHITE);
+                let pill = egui::Rect::from_center_size(polyline_midpoint(&points), galley.size() + egui::vec2(12.0, 4.0) * self.zoom);
+                let background = egui::Color32::from_hex("#30313c").unwrap();
+                painter.rect(pill, pill.height() / 2.0, background, egui::Stroke::new(1.0, stroke.color), egui::StrokeKind::Outside);
+                painter.galley(pill.center() - galley.size() / 2.0, galley, egui::Color32::WHITE);
+            }
+        }
+    }
+
+    /// Bring the spatial index up to date with the map. The whole map is
+    /// checked only after the history changed it; otherwise just the nodes
+    /// marked stale, or their positions while a layout runs. Only nodes that
+    /// moved or changed size are measured again, and only the edges between
+    /// them and their neighbours are re-routed.
+    fn sync_spatial_index(&mut self, ctx: &egui::Context) {
+        let index = &mut self.spatial_index;
+        if index.routing != self.edge_routing {
+            *index = SpatialIndex::new(self.edge_routing);
+        }
+
+        let edited = index.synced != Some(self.history.generation());
+        let moving = self.layout.is_some();
+        if !edited && !moving && index.stale.is_empty() {
+            return;
+        }
+        index.synced = Some(self.history.generation());
+        let stale = std::mem::take(&mut index.stale);
+
+        let mut changed = std::collections::HashSet::new();
+        for (order, node) in self.map.nodes.iter().enumerate() {
+            let measure = edited || stale.contains(&node.id);
+            if !measure && !moving {
+                continue;
+            }
+            let scale = self.node_scale.get(&node.id).copied().unwrap_or(1.0);
+            let center = egui::pos2(node.x, node.y);
+            let shape = measure.then(|| NodeShape::of(node, scale));
+            let rect = match index.nodes.get_mut(&node.id) {
+                Some(indexed) if shape.is_none_or(|shape| shape == indexed.shape) => {
+                    indexed.order = order;
+                    if indexed.center == center {
+                        continue;
+                    }
+                    indexed.center = center;
+                    indexed.rect = egui::Rect::from_center_size(center, indexed.rect.size());
+                    indexed.rect
+                }
+                _ => {
+                    // Node rects are kept at zoom 1, the canvas scales them when drawing
+                    let rect = egui::Rect::from_center_size(center, get_node_rect(ctx, node, scale).size());
+                    let shape = shape.unwrap_or_else(|| NodeShape::of(node, scale));
+                    index.nodes.insert(node.id, IndexedNode { order, center, rect, shape });
+                    rect
+                }
+            };
+            index.node_grid.insert_box(node.id, bounds(rect));
+            changed.insert(node.id);
+        }
+        if index.nodes.len() > self.map.nodes.len() {
+            let ids: std::collections::HashSet<Uuid> = self.map.nodes.iter().map(|n| n.id).collect();
+            let removed: Vec<Uuid> = index.nodes.keys().filter(|id| !ids.contains(id)).copied().collect();
+            for id in removed {
+                index.nodes.remove(&id);
+                index.node_grid.remove(id);
+                changed.insert(id);
+            }
+        }
+
+        // Frames are measured around their nodes. Nodes in a collapsed frame are
+        // hidden, and their edges end at the frame's box instead.
+        index.frames.clear();
+        let mut covered = std::collections::HashMap::new();
+        for frame in &self.map.frames {
+            let Some(area) = frame.nodes.iter()
+                .filter_map(|id| index.nodes.get(id).map(|n| n.rect))
+                .reduce(|a, b| a.union(b)) else { continue };
+            let rect = if frame.collapsed {
+                let rect = egui::Rect::from_center_size(area.center(), FRAME_SUMMARY_SIZE);
+                for id in &frame.nodes {
+                    covered.entry(*id).or_insert((frame.id, rect));
+                }
+                rect
+            } else {
+                let rect = area.expand(FRAME_PADDING);
+                rect.with_min_y(rect.min.y - FRAME_LABEL_HEIGHT)
+            };
+            index.frames.insert(frame.id, rect);
+        }
+        for (id, cover) in &covered {
+            if index.covered.get(id) != Some(cover) {
+                changed.insert(*id);
+            }
+        }
+        for id in index.covered.keys() {
+            if !covered.contains_key(id) {
+                changed.insert(*id);
+            }
+        }
+        index.covered = covered;
+
+        // Edges between the same two nodes are routed together, as they fan out
+        let pair = |from: Uuid, to: Uuid| if from < to { (from, to) } else { (to, from) };
+        let mut dirty = std::collections::HashSet::new();
+        let mut known = 0;
+        for edge in &self.map.edges {
+            match index.edges.get(&edge.id) {
+                Some(indexed) => {
+                    known += 1;
+                    if indexed.from != edge.from || indexed.to != edge.to {
+                        dirty.insert(pair(indexed.from, indexed.to));
+                        dirty.insert(pair(edge.from, edge.to));
+                    }
+                }
+                None => {
+                    dirty.insert(pair(edge.from, edge.to));
+                }
+            }
+            if changed.contains(&edge.from) || changed.contains(&edge.to) {
+                dirty.insert(pair(edge.from, edge.to));
+            }
+        }
+        if index.edges.len() > known {
+            let ids: std::collections::HashSet<Uuid> = self.map.edges.iter().map(|e| e.id).collect();
+            let removed: Vec<Uuid> = index.edges.keys().filter(|id| !ids.contains(id)).copied().collect();
+            for id in removed {
+                if let Some(indexed) = index.edges.remove(&id) {
+                    dirty.insert(pair(indexed.from, indexed.to));
+                }
+                index.edge_grid.remove(id);
+            }
+        }
+        if dirty.is_empty() {
+            return;
+        }
+
+        let mut parallel: std::collections::HashMap<(Uuid, Uuid), usize> = std::collections::HashMap::new();
+        for edge in &self.map.edges {
+            let key = pair(edge.from, edge.to);
+            if dirty.contains(&key) {
+                *parallel.entry(key).or_default() += 1;
+            }
+        }
+        let mut placed: std::collections::HashMap<(Uuid, Uuid), usize> = std::collections::HashMap::new();
+        for edge in &self.map.edges {
+            let key = pair(edge.from, edge.to);
+            if !dirty.contains(&key) {
+                continue;
+            }
+            let position = placed.entry(key).or_default();
+            let fan = (*position as f32 - (parallel[&key] - 1) as f32 / 2.0) * EDGE_FAN_SPACING;
+            *position += 1;
+
+            let end = |id: &Uuid| index.covered.get(id).map(|(_, rect)| *rect).or(index.nodes.get(id).map(|n| n.rect));
+            let same_frame = index.covered.get(&edge.from).zip(index.covered.get(&edge.to)).is_some_and(|(a, b)| a.0 == b.0);
+            let points = match (end(&edge.from), end(&edge.to)) {
+                // Edges inside a collapsed frame are hidden with its nodes
+                _ if same_frame => Vec::new(),
+                (Some(from), Some(_)) if edge.from == edge.to => self_loop(from, *position as f32),
+                (Some(from), Some(to)) => {
+                    // Sideways direction shared by both directions of a pair, so fanned edges don't cross
+                    let (first, second) = if edge.from == key.0 { (from, to) } else { (to, from) };
+                    let along = (second.center() - first.center()).normalized();
+                    route_edge(from, to, self.edge_routing, egui::vec2(-along.y, along.x) * fan)
                 }
+                // Edges to missing nodes are not drawn
+                _ => Vec::new(),
+            };
+            let line: Vec<[f32; 2]> = points.iter().map(|p| [p.x, p.y]).collect();
+            index.edge_grid.insert_line(edge.id, &line);
+            index.edges.insert(edge.id, IndexedEdge { from: edge.from, to: edge.to, points });
+        }
+    }
 
-                // Draw line
-                painter.line_segment([p1, p2], egui::Stroke::new(width, egui::Color32::from_rgba_unmultiplied(fill[0], fill[1], fill[2], fill[3])));
+    /// The part of the map shown on the canvas `rect`, in canvas coordinates.
+    fn visible_canvas_rect(&self, rect: egui::Rect) -> egui::Rect {
+        egui::Rect::from_min_max((-self.pan / self.zoom).to_pos2(), ((rect.size() - self.pan) / self.zoom).to_pos2())
+    }
+
+    /// Nodes whose rect contains `canvas_pos`, in map order.
+    fn nodes_at(&self, canvas_pos: Pos2) -> Vec<Uuid> {
+        let index = &self.spatial_index;
+        let mut found: Vec<(usize, Uuid)> = index.node_grid.query(bounds(egui::Rect::from_center_size(canvas_pos, egui::Vec2::ZERO)))
+            .into_iter()
+            .filter_map(|id| {
+                let indexed = index.nodes.get(&id)?;
+                (indexed.rect.contains(canvas_pos) && !index.covered.contains_key(&id)).then_some((indexed.order, id))
+            })
+            .collect();
+        found.sort();
+        found.into_iter().map(|(_, id)| id).collect()
+    }
 
-                // Draw arrowhead for References
-                if edge.edge_type == EdgeType::References {
-                    let arrow_size = 15.0; // Size of the arrowhead
-                    let angle = std::f32::consts::PI / 6.0; // 30 degrees
-                    let dir = (p2 - p1).normalized(); // Direction of the edge
-                    let perp = egui::Vec2::new(-dir.y, dir.x); // Perpendicular vector
+    fn node_at(&self, canvas_pos: Pos2) -> Option<Uuid> {
+        self.nodes_at(canvas_pos).first().copied()
+    }
 
-                    // Calculate the midpoint of the edge
-                    let midpoint = (p1 + p2.to_vec2()) * 0.5;
+    /// Where a node dragged to `position` ends up: on the grid when snapping
+    /// is on, and lined up with a node on screen when one of its edges or its
+    /// center comes within a few pixels of theirs. Lining up wins over the
+    /// grid. The guides for what it lines up with are kept for drawing.
+    fn snap_position(&mut self, node_id: Uuid, position: Pos2) -> Pos2 {
+        self.alignment_guides.clear();
+        let index = &self.spatial_index;
+        let Some(size) = index.nodes.get(&node_id).map(|n| n.rect.size()) else { return position };
+
+        let grid = self.map.grid;
+        let mut snapped = if grid.snap && grid.size > 0.0 {
+            egui::pos2(snap_to_grid(position.x, grid.size), snap_to_grid(position.y, grid.size))
+        } else {
+            position
+        };
 
-                    // Calculate the arrowhead points
-                    let p1_arrow = midpoint - dir * arrow_size * 0.5; // Base of the arrowhead
-                    let p2_arrow = midpoint + dir * arrow_size * 0.5; // Tip of the arrowhead
-                    let p3_arrow = p1_arrow + perp * arrow_size * angle.tan(); // One side of the arrowhead
-                    let p4_arrow = p1_arrow - perp * arrow_size * angle.tan(); // Other side of the arrowhead
+        let visible = self.visible_canvas_rect(self.canvas_rect);
+        let others: Vec<egui::Rect> = index.node_grid.query(bounds(visible))
+            .into_iter()
+            .filter(|id| *id != node_id && !index.covered.contains_key(id))
+            .filter_map(|id| index.nodes.get(&id).map(|n| n.rect))
+            .filter(|r| r.intersects(visible))
+            .collect();
+        let tolerance = GUIDE_SNAP_DISTANCE / self.zoom;
+        let rect = egui::Rect::from_center_size(position, size);
+        let xs = |r: &egui::Rect| [r.left(), r.center().x, r.right()];
+        let ys = |r: &egui::Rect| [r.top(), r.center().y, r.bottom()];
+        let x_lines: Vec<[f32; 3]> = others.iter().map(xs).collect();
+        let y_lines: Vec<[f32; 3]> = others.iter().map(ys).collect();
+        if let Some(shift) = alignment(xs(&rect), &x_lines, tolerance) {
+            snapped.x = position.x + shift;
+        }
+        if let Some(shift) = alignment(ys(&rect), &y_lines, tolerance) {
+            snapped.y = position.y + shift;
+        }
 
-                    painter.line_segment([p2_arrow, p3_arrow], egui::Stroke::new(width, egui::Color32::from_rgba_unmultiplied(fill[0], fill[1], fill[2], fill[3]))); // Line from tip to one side
-                    painter.line_segment([p2_arrow, p4_arrow], egui::Stroke::new(width, egui::Color32::from_rgba_unmultiplied(fill[0], fill[1], fill[2], fill[3]))); // Line from tip to other side
+        // A guide for every line the node now shares with another node,
+        // spanning both of them
+        let rect = egui::Rect::from_center_size(snapped, size);
+        for other in &others {
+            let span = rect.union(*other);
+            for a in xs(&rect) {
+                if xs(other).iter().any(|b| (a - b).abs() < 0.01) {
+                    self.alignment_guides.push([egui::pos2(a, span.top()), egui::pos2(a, span.bottom())]);
+                }
+            }
+            for a in ys(&rect) {
+                if ys(other).iter().any(|b| (a - b).abs() < 0.01) {
+                    self.alignment_guides.push([egui::pos2(span.left(), a), egui::pos2(span.right(), a)]);
                 }
             }
         }
+        snapped
+    }
+
+    /// The frame grabbed at `canvas_pos`: by its label strip, or anywhere on
+    /// the box of a collapsed frame. Later frames are on top.
+    fn frame_at(&self, canvas_pos: Pos2) -> Option<Uuid> {
+        self.map.frames.iter().rev()
+            .find(|frame| self.spatial_index.frames.get(&frame.id).is_some_and(|rect| {
+                let handle = if frame.collapsed { *rect } else { rect.with_max_y(rect.min.y + FRAME_LABEL_HEIGHT) };
+                handle.contains(canvas_pos)
+            }))
+            .map(|frame| frame.id)
+    }
+
+    /// The edge drawn closest to `canvas_pos`, if any is close enough to click.
+    fn edge_at(&self, canvas_pos: Pos2) -> Option<Uuid> {
+        // Edges can be clicked from a few pixels away at any zoom
+        let tolerance = 8.0 / self.zoom;
+        let index = &self.spatial_index;
+        index.edge_grid.query(bounds(egui::Rect::from_center_size(canvas_pos, egui::Vec2::splat(tolerance * 2.0))))
+            .into_iter()
+            .filter_map(|id| {
+                let distance = index.edges.get(&id)?.points.windows(2)
+                    .map(|w| point_line_distance(w[0], w[1], canvas_pos))
+                    .fold(f32::INFINITY, f32::min);
+                (distance < tolerance).then_some((id, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id)
     }
 
     fn draw_pending_edges(&mut self, rect: egui::Rect, painter: &egui::Painter) {
-        if let (Some(from), Some(to)) = (self.pending_edge_from, self.pending_edge_to) {
-            if let (Some(f), Some(t)) = (
+        if let (Some(from), Some(to)) = (self.pending_edge_from, self.pending_edge_to)
+            && let (Some(f), Some(t)) = (
                 self.map.nodes.iter().find(|n| n.id == from),
                 self.map.nodes.iter().find(|n| n.id == to),
-            ) {
-                let p1 = egui::pos2(f.x, f.y) * self.zoom + self.pan + rect.min.to_vec2();
-                let p2 = egui::pos2(t.x, t.y) * self.zoom + self.pan + rect.min.to_vec2();
-                painter.line_segment([p1, p2], egui::Stroke::new(1.5, egui::Color32::LIGHT_GRAY));
-            }
+            )
+        {
+            let p1 = egui::pos2(f.x, f.y) * self.zoom + self.pan + rect.min.to_vec2();
+            let p2 = egui::pos2(t.x, t.y) * self.zoom + self.pan + rect.min.to_vec2();
+            painter.line_segment([p1, p2], egui::Stroke::new(1.5, egui::Color32::LIGHT_GRAY));
         }
     }
 
     fn draw_connection_line(&mut self, rect: egui::Rect, painter: &egui::Painter, response: &egui::Response) {
-        if let Some(start_id) = self.connecting_from {
-            if let Some(pointer_pos) = response.interact_pointer_pos() {
-                if let Some(start_node) = self.map.nodes.iter().find(|n| n.id == start_id) {
-                    let p1 = egui::pos2(start_node.x, start_node.y) * self.zoom
-                        + self.pan
-                        + rect.min.to_vec2();
-                    let p2 = pointer_pos;
-                    painter.line_segment([p1, p2], egui::Stroke::new(1.5, egui::Color32::LIGHT_GRAY));
-                }
-            }
+        if let Some(start_id) = self.connecting_from
+            && let Some(pointer_pos) = response.interact_pointer_pos()
+            && let Some(start_node) = self.map.nodes.iter().find(|n| n.id == start_id)
+        {
+            let p1 = egui::pos2(start_node.x, start_node.y) * self.zoom
+                + self.pan
+                + rect.min.to_vec2();
+            let p2 = pointer_pos;
+            painter.line_segment([p1, p2], egui::Stroke::new(1.5, egui::Color32::LIGHT_GRAY));
         }
     }
 
     fn draw_nodes(&mut self, rect: egui::Rect, painter: &egui::Painter, ctx: &egui::Context) {
-        for node in &mut self.map.nodes {
-            let pos = egui::pos2(node.x, node.y) * self.zoom + self.pan + rect.min.to_vec2();
-            let font_id = egui::FontId::proportional(14.0 * self.zoom);
-            let bold_font_id = egui::FontId::monospace(14.0 * self.zoom);
-
-            let padding = egui::vec2(16.0, 12.0) * self.zoom;
-
-            // Calculate node size based on collapse state
-            let mut node_size = if node.collapsed {
-                // When collapsed, size based on title
-                let title_galley = ctx.fonts_mut(|f| f.layout_no_wrap(node.title.clone(), font_id.clone(), egui::Color32::BLACK));
-                let text_size = title_galley.size();
-                egui::vec2(text_size.x + padding.x * 2.0, text_size.y + padding.y)
-            } else {
-                // When expanded, use minimum width or title width, whichever is larger
-                let title_galley = ctx.fonts_mut(|f| f.layout_no_wrap(node.title.clone(), font_id.clone(), egui::Color32::BLACK));
-                let title_width = title_galley.size().x + padding.x * 2.0;
-                let min_width = 300.0 * self.zoom;
-                let node_width = title_width.max(min_width);
-                egui::vec2(node_width, 0.0) // Height will be calculated below
-            };
-
-            let mut metadata_galleys = Vec::new();
-            if !node.collapsed {
-                if let Some(metadata) = &node.metadata {
-                    let max_width = node_size.x - padding.x * 2.0;
-
-                    // Create layout for each metadata field
-                    let authors_str = metadata.authors.join(", ");
-                    let keywords_str = metadata.keywords.join(", ");
-                    let fields = vec![
-                        ("Title: ", &metadata.title),
-                        ("Authors: ", &authors_str),
-                        ("Keywords: ", &keywords_str),
-                        ("Date: ", &metadata.date),
-                    ];
-
-                    // Find the widest label to align all values
-                    let label_width = Self::find_widest_label(ctx, fields.clone(), self.zoom);
-
-                    for (label, value) in fields {
-                        // Create label galley (bold)
-                        let label_galley = ctx.fonts_mut(|f|
-                            f.layout_no_wrap(label.to_string(), bold_font_id.clone(), egui::Color32::BLACK)
-                        );
-
-                        // Create wrapped value galley with remaining width
-                        let value_galley = ctx.fonts_mut(|f|
-                            f.layout(
-                                value.to_string(),
-                                font_id.clone(),
-                                egui::Color32::BLACK,
-                                max_width - label_width
-                            )
-                        );
-
-                        metadata_galleys.push((label_galley, value_galley, label_width));
-                    }
+        // Nodes off screen are skipped. The margin keeps icons that stick out
+        // of a node visible while the node itself is just off screen.
+        let visible = self.visible_canvas_rect(rect).expand(NODE_ICON_MARGIN / self.zoom);
+        let mut visible_nodes: Vec<(usize, NodeShape)> = self.spatial_index.node_grid.query(bounds(visible))
+            .into_iter()
+            .filter(|id| !self.spatial_index.covered.contains_key(id))
+            .filter_map(|id| self.spatial_index.nodes.get(&id))
+            .filter(|indexed| indexed.rect.intersects(visible))
+            .map(|indexed| (indexed.order, indexed.shape))
+            .collect();
+        // Later nodes are drawn on top, as before culling
+        visible_nodes.sort_unstable_by_key(|(order, _)| *order);
+
+        // egui rebuilds the font atlas when it fills up, which leaves cached
+        // galleys pointing at glyphs that are gone
+        if ctx.fonts(|f| f.font_atlas_fill_ratio()) < self.font_atlas_fill {
+            self.node_layouts.clear();
+        }
+        if self.node_layouts.len() > self.map.nodes.len() {
+            let ids: std::collections::HashSet<Uuid> = self.map.nodes.iter().map(|n| n.id).collect();
+            self.node_layouts.retain(|id, _| ids.contains(id));
+        }
 
-                    // Calculate total height needed
-                    let line_spacing = 4.0 * self.zoom;
-                    node_size.y = padding.y;
-                    for (_, value_galley, _) in &metadata_galleys {
-                        node_size.y += value_galley.size().y + line_spacing;
-                    }
-                    node_size.y += padding.y;
-                } else {
-                    // No metadata, just show title with calculated width
-                    let title_galley = ctx.fonts_mut(|f| f.layout_no_wrap(node.title.clone(), font_id.clone(), egui::Color32::BLACK));
-                    node_size.y = title_galley.size().y + padding.y;
-                }
+        for (i, shape) in visible_nodes {
+            let Some(node) = self.map.nodes.get(i) else { continue };
+            let pos = egui::pos2(node.x, node.y) * self.zoom + self.pan + rect.min.to_vec2();
+            // Analytics can scale nodes by a metric, everything inside the node scales along
+            let zoom = self.zoom * shape.scale;
+            let font_id = egui::FontId::proportional(14.0 * zoom);
+            let padding = egui::vec2(16.0, 12.0) * zoom;
+
+            // Text is laid out again only when the node or the zoom changed
+            let cached = self.node_layouts.get(&node.id).is_some_and(|l| l.zoom == self.zoom && l.shape == shape);
+            if !cached {
+                self.node_layouts.insert(node.id, NodeLayout::new(ctx, node, self.zoom, shape));
             }
+            let layout = &self.node_layouts[&node.id];
+            let node_size = layout.size;
 
             let node_rect = egui::Rect::from_center_size(pos, node_size);
 
@@ -751,8 +1416,8 @@ impl MindMapApp {
 
             // Draw annotation icon if node has annotations
             if !node.annotations.is_empty(){
-                let icon_size = egui::vec2(16.0, 16.0) * self.zoom;
-                let icon_pos = node_rect.right_top() - egui::vec2(5.0, 5.0) * self.zoom;
+                let icon_size = egui::vec2(16.0, 16.0) * zoom;
+                let icon_pos = node_rect.right_top() - egui::vec2(5.0, 5.0) * zoom;
                 let icon_rect = egui::Rect::from_min_size(icon_pos, icon_size);
 
                 // Draw a small rectangle with a note symbol
@@ -760,12 +1425,18 @@ impl MindMapApp {
                 painter.text(icon_rect.center(), egui::Align2::CENTER_CENTER, "📝", font_id.clone(), egui::Color32::BLACK);
             }
 
-            if !node.collapsed && !metadata_galleys.is_empty() {
+            // Draw pin icon if automatic layouts should leave the node alone
+            if node.pinned {
+                let icon_pos = node_rect.left_top() + egui::vec2(2.0, 2.0) * zoom;
+                painter.text(icon_pos, egui::Align2::CENTER_CENTER, "📌", egui::FontId::proportional(12.0 * zoom), egui::Color32::BLACK);
+            }
+
+            if !node.collapsed && !layout.metadata.is_empty() {
                 // Draw metadata
                 let mut y_offset = -node_size.y/2.0 + padding.y;
                 let x_pos = pos.x - node_size.x/2.0 + padding.x;
 
-                for (label_galley, value_galley, label_width) in metadata_galleys {
+                for (label_galley, value_galley, label_width) in &layout.metadata {
                     // Draw label (bold)
                     painter.galley(
                         egui::pos2(x_pos, pos.y + y_offset),
@@ -775,20 +1446,18 @@ impl MindMapApp {
 
                     // Draw value (normal, wrapped) aligned after the widest label
                     painter.galley(
-                        egui::pos2(x_pos + label_width, pos.y + y_offset),
+                        egui::pos2(x_pos + *label_width, pos.y + y_offset),
                         value_galley.clone(),
                         egui::Color32::BLACK
                     );
 
-                    y_offset += value_galley.size().y + 4.0 * self.zoom;
+                    y_offset += value_galley.size().y + 4.0 * zoom;
                 }
             } else {
                 // Draw just the title
-                painter.text(
-                    pos,
-                    egui::Align2::CENTER_CENTER,
-                    &node.title,
-                    font_id.clone(),
+                painter.galley(
+                    pos - layout.title.size() / 2.0,
+                    layout.title.clone(),
                     egui::Color32::BLACK
                 );
             }
@@ -797,16 +1466,80 @@ impl MindMapApp {
             let mut tag_offset = 0.0;
             for tag in &node.tags {
                 if let Some(tag) = self.map.tags.iter().find(|t| t.id == *tag) {
-                    let tag_size = egui::vec2(10.0, 10.0) * self.zoom;
-                    let tag_pos = node_rect.right_bottom() - egui::vec2(5.0 + tag_offset, 5.0) * self.zoom;
+                    let tag_size = egui::vec2(10.0, 10.0) * zoom;
+                    let tag_pos = node_rect.right_bottom() - egui::vec2(5.0 + tag_offset, 5.0) * zoom;
                     let tag_rect = egui::Rect::from_min_size(tag_pos, tag_size);
 
                     painter.rect_filled(tag_rect, 5.0, egui::Color32::from_rgba_unmultiplied(tag.color[0], tag.color[1], tag.color[2], tag.color[3]));
 
-                    tag_offset += 15.0 * self.zoom;
+                    tag_offset += 15.0 * zoom;
                 }
             }
         }
+        self.font_atlas_fill = ctx.fonts(|f| f.font_atlas_fill_ratio());
+    }
+
+    /// Overview of the whole map in a corner of the canvas. The frame marks
+    /// the part of the map on screen; clicking or dragging centers the canvas
+    /// on that point.
+    fn draw_minimap(&mut self, painter: &egui::Painter, response: &egui::Response, rect: egui::Rect) {
+        let minimap_rect = response.rect;
+        let index = &self.spatial_index;
+        // Nodes hidden in a collapsed frame show up as the frame's box, as on the canvas
+        let mut boxes: Vec<([u8; 4], egui::Rect)> = self.map.frames.iter()
+            .filter(|frame| frame.collapsed)
+            .filter_map(|frame| Some((frame.color, *index.frames.get(&frame.id)?)))
+            .collect();
+        boxes.extend(self.map.nodes.iter()
+            .filter(|node| !index.covered.contains_key(&node.id))
+            .filter_map(|node| Some((node.color.unwrap_or(egui::Color32::LIGHT_BLUE.to_array()), index.nodes.get(&node.id)?.rect))));
+
+        // Keep the scale while dragging, or the map would slide under the pointer
+        let bounds = match self.minimap_bounds {
+            Some(bounds) if response.dragged() => bounds,
+            _ => boxes.iter()
+                .fold(self.visible_canvas_rect(rect), |bounds, (_, box_rect)| bounds.union(*box_rect))
+                .expand2(rect.size() / self.zoom * 0.05),
+        };
+        self.minimap_bounds = response.dragged().then_some(bounds);
+        let scale = (minimap_rect.width() / bounds.width()).min(minimap_rect.height() / bounds.height());
+        let to_minimap = |pos: Pos2| minimap_rect.center() + (pos - bounds.center()) * scale;
+
+        if (response.clicked() || response.dragged()) && let Some(pointer_pos) = response.interact_pointer_pos() {
+            let target = bounds.center() + (pointer_pos - minimap_rect.center()) / scale;
+            self.pan = rect.size() / 2.0 - target.to_vec2() * self.zoom;
+        }
+
+        let painter = painter.with_clip_rect(minimap_rect);
+        painter.rect(
+            minimap_rect,
+            4.0,
+            egui::Color32::from_rgba_unmultiplied(20, 20, 28, 230),
+            egui::Stroke::new(1.0, egui::Color32::GRAY),
+            egui::StrokeKind::Inside,
+        );
+
+        // Edges follow their routes on the canvas, so edges into a collapsed
+        // frame end at its box and edges inside it are left out
+        for edge in &self.map.edges {
+            let Some(indexed) = index.edges.get(&edge.id).filter(|e| e.points.len() > 1) else { continue };
+            let points = indexed.points.iter().map(|p| to_minimap(*p)).collect();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, egui::Color32::DARK_GRAY)));
+        }
+
+        for (fill, box_rect) in &boxes {
+            // Tiny nodes are still drawn a couple of pixels wide
+            let size = (box_rect.size() * scale).max(egui::vec2(2.0, 2.0));
+            painter.rect_filled(egui::Rect::from_center_size(to_minimap(box_rect.center()), size), 0.0, color32(*fill));
+        }
+
+        let view = self.visible_canvas_rect(rect);
+        painter.rect_stroke(
+            egui::Rect::from_min_max(to_minimap(view.min), to_minimap(view.max)),
+            0.0,
+            egui::Stroke::new(1.5, egui::Color32::WHITE),
+            egui::StrokeKind::Middle,
+        );
     }
 
     fn draw_marquee_rect(&mut self, painter: &egui::Painter) {
@@ -839,19 +1572,26 @@ impl MindMapApp {
                 ui.menu_button("File", |ui| {
                     if ui.button("New").clicked() {
                         self.map = Default::default();
+                        self.layout = None;
+                        self.citation_scans.clear();
+                        self.link_proposals.clear();
+                        self.duplicate_groups.clear();
+                        self.highlighted_path.clear();
+                        self.show_edge_types_dialog = false;
+                        self.history.clear();
                         self.current_file = None;
+                        self.indexer = None;
+                        self.search_index = None;
+                        self.search_results.clear();
                         ui.close_kind(UiKind::Menu);
                     }
 
                     if ui.button("Open...").clicked() {
-                        if let Some(project_dir) = FileDialog::new().pick_folder() {
-                            if let Ok(loaded_map) = load_map(project_dir.to_str().unwrap()) {
-                                self.current_file = Some(project_dir.to_str().unwrap().to_string());
-                                if let Err(e) = save_last_file(&self.current_file.as_ref().unwrap()) {
-                                    eprintln!("Failed to save last file: {}", e);
-                                }
-                                self.map = loaded_map;
-                            }
+                        if let Some(project_dir) = FileDialog::new().pick_folder()
+                            && self.open_project(project_dir.to_str().unwrap())
+                            && let Err(e) = save_last_file(self.current_file.as_ref().unwrap())
+                        {
+                            eprintln!("Failed to save last file: {}", e);
                         }
                         ui.close_kind(UiKind::Menu);
                     }
@@ -861,15 +1601,51 @@ impl MindMapApp {
                         ui.close_kind(UiKind::Menu);
                     }
 
+                    if ui.button("Import Project...").clicked() {
+                        self.import_project();
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    ui.separator();
+
+                    ui.menu_button("Import References", |ui| {
+                        for format in Format::ALL {
+                            if ui.button(format!("{}...", format.name())).clicked() {
+                                if let Some(path) = FileDialog::new()
+                                    .add_filter(format.name(), format.extensions())
+                                    .pick_file()
+                                {
+                                    self.import_references(format, &path);
+                                }
+                                ui.close_kind(UiKind::Menu);
+                            }
+                        }
+                    });
+
+                    ui.menu_button("Export References", |ui| {
+                        for format in Format::ALL {
+                            ui.menu_button(format.name(), |ui| {
+                                if ui.button("All Nodes...").clicked() {
+                                    self.export_references(format, false);
+                                    ui.close_kind(UiKind::Menu);
+                                }
+
+                                if ui.add_enabled(!self.selected_nodes.is_empty(), egui::Button::new("Selected Nodes...")).clicked() {
+                                    self.export_references(format, true);
+                                    ui.close_kind(UiKind::Menu);
+                                }
+                            });
+                        }
+                    });
+
                     if ui.button("Export Project...").clicked() {
                         if let Some(project_dir) = &self.current_file {
                             if let Some(zip_path) = FileDialog::new()
                                 .add_filter("ZIP", &["zip"])
                                 .save_file()
+                                && let Err(e) = export_project(project_dir, zip_path.to_str().unwrap())
                             {
-                                if let Err(e) = export_project(project_dir, zip_path.to_str().unwrap()) {
-                                    eprintln!("Failed to export project: {}", e);
-                                }
+                                eprintln!("Failed to export project: {}", e);
                             }
                         } else {
                             eprintln!("No project is currently open.");
@@ -877,6 +1653,126 @@ impl MindMapApp {
                         ui.close_kind(UiKind::Menu);
                     }
                 });
+
+                ui.menu_button("Edit", |ui| {
+                    if ui.add_enabled(self.history.can_undo(), egui::Button::new("Undo").shortcut_text("Ctrl+Z")).clicked() {
+                        self.undo();
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    if ui.add_enabled(self.history.can_redo(), egui::Button::new("Redo").shortcut_text("Ctrl+Shift+Z")).clicked() {
+                        self.redo();
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    ui.separator();
+
+                    if ui.add_enabled(!self.selected_nodes.is_empty(), egui::Button::new("Group Selection in Frame...")).clicked() {
+                        self.open_frame_dialog(None, self.selected_nodes.clone());
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    if ui.button("Edge Types...").clicked() {
+                        self.open_edge_types_dialog();
+                        ui.close_kind(UiKind::Menu);
+                    }
+                });
+
+                ui.menu_button("Layout", |ui| {
+                    if ui.button("Force-directed").clicked() {
+                        self.start_force_layout(ctx, false);
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    if ui.add_enabled(self.selected_nodes.len() > 1, egui::Button::new("Force-directed (Selected Nodes)")).clicked() {
+                        self.start_force_layout(ctx, true);
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    ui.menu_button("Layered", |ui| {
+                        if ui.button("Cited Papers Above").clicked() {
+                            self.apply_layered_layout(ctx, true);
+                            ui.close_kind(UiKind::Menu);
+                        }
+
+                        if ui.button("Cited Papers Below").clicked() {
+                            self.apply_layered_layout(ctx, false);
+                            ui.close_kind(UiKind::Menu);
+                        }
+                    });
+
+                    ui.separator();
+
+                    if ui.add_enabled(self.layout.is_some(), egui::Button::new("Stop Layout")).clicked() {
+                        self.finish_layout();
+                        ui.close_kind(UiKind::Menu);
+                    }
+                });
+
+                ui.menu_button("View", |ui| {
+                    if ui.checkbox(&mut self.show_analytics_panel, "Graph Analytics").clicked() {
+                        ui.close_kind(UiKind::Menu);
+                    }
+                    if ui.checkbox(&mut self.show_minimap, "Minimap").clicked() {
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    ui.separator();
+
+                    if ui.add_enabled(self.selected_nodes.len() == 2, egui::Button::new("Shortest Path Between Selected")).clicked() {
+                        self.show_shortest_path();
+                        ui.close_kind(UiKind::Menu);
+                    }
+                    ui.checkbox(&mut self.path_directed, "Follow Edge Direction");
+                    if ui.add_enabled(!self.highlighted_path.is_empty(), egui::Button::new("Clear Path")).clicked() {
+                        self.highlighted_path.clear();
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    ui.separator();
+
+                    ui.menu_button("Grid", |ui| {
+                        // Grid settings are saved with the project
+                        let grid = &mut self.map.grid;
+                        let mut changed = ui.checkbox(&mut grid.visible, "Show Grid").changed();
+                        changed |= ui.checkbox(&mut grid.snap, "Snap to Grid").changed();
+                        ui.horizontal(|ui| {
+                            ui.label("Size:");
+                            changed |= ui.add(egui::DragValue::new(&mut grid.size).range(5.0..=500.0)).changed();
+                        });
+                        if changed {
+                            self.dirty = true;
+                        }
+                    });
+
+                    ui.menu_button("Edge Routing", |ui| {
+                        for routing in EdgeRouting::ALL {
+                            if ui.radio_value(&mut self.edge_routing, routing, routing.name()).clicked() {
+                                ui.close_kind(UiKind::Menu);
+                            }
+                        }
+                    });
+                });
+
+                ui.menu_button("Search", |ui| {
+                    if ui.add(egui::Button::new("Search PDFs...").shortcut_text("Ctrl+F")).clicked() {
+                        self.show_search_panel = true;
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    if ui.add_enabled(self.current_file.is_some() && self.indexer.is_none(), egui::Button::new("Rebuild Index")).clicked() {
+                        self.start_indexing();
+                        ui.close_kind(UiKind::Menu);
+                    }
+
+                    ui.separator();
+
+                    if ui.button("Find Duplicates...").clicked() {
+                        self.find_duplicates();
+                        self.show_duplicates = true;
+                        ui.close_kind(UiKind::Menu);
+                    }
+                });
             });
         });
     }
@@ -902,6 +1798,7 @@ impl MindMapApp {
                             if ui.button("View Annotations").clicked() {
                                 if let Some(node_id) = self.rightclick_node {
                                     self.annotations_node_id = Some(node_id);
+                                    self.annotations_frame_id = None;
                                     self.show_annotations_panel = true;
                                 }
                                 self.show_node_context_menu = false;
@@ -910,6 +1807,7 @@ impl MindMapApp {
                             if ui.button("Add Annotation").clicked() {
                                 if let Some(node_id) = self.rightclick_node {
                                     self.annotations_node_id = Some(node_id);
+                                    self.annotations_frame_id = None;
                                     self.edit_annotation = EditableAnnotation::default();
                                     self.show_add_annotation_dialog = true;
                                 }
@@ -946,12 +1844,65 @@ impl MindMapApp {
                                 self.show_node_context_menu = false;
                             }
 
+                            let pinned = self.rightclick_node
+                                .and_then(|id| self.map.nodes.iter().find(|n| n.id == id))
+                                .is_some_and(|n| n.pinned);
+                            if ui.button(if pinned { "Unpin Position" } else { "Pin Position" }).clicked() {
+                                if let Some(node_id) = self.rightclick_node {
+                                    self.update_node(node_id, |node| node.pinned = !pinned);
+                                }
+                                self.show_node_context_menu = false;
+                            }
+
+                            let can_scan = self.rightclick_node
+                                .and_then(|id| self.map.nodes.iter().find(|n| n.id == id))
+                                .is_some_and(|n| n.path.is_some() && !self.citation_scans.iter().any(|scan| scan.node_id == n.id));
+                            if ui.add_enabled(can_scan, egui::Button::new("Find Cited Papers")).clicked() {
+                                if let Some(node_id) = self.rightclick_node {
+                                    self.scan_citations(node_id, true);
+                                }
+                                self.show_node_context_menu = false;
+                            }
+
+                            if ui.button("Group in Frame...").clicked() {
+                                if let Some(node_id) = self.rightclick_node {
+                                    // The whole selection is grouped when the node is part of it
+                                    let nodes = if self.selected_nodes.contains(&node_id) { self.selected_nodes.clone() } else { vec![node_id] };
+                                    self.open_frame_dialog(None, nodes);
+                                }
+                                self.show_node_context_menu = false;
+                            }
+
+                            let in_frame = self.rightclick_node.is_some_and(|id| self.map.frames.iter().any(|f| f.nodes.contains(&id)));
+                            if in_frame && ui.button("Remove from Frame").clicked() {
+                                if let Some(node_id) = self.rightclick_node {
+                                    // Frames left without nodes are removed, last to first so indices stay valid
+                                    let commands = self.map.frames.iter()
+                                        .enumerate()
+                                        .rev()
+                                        .filter(|(_, f)| f.nodes.contains(&node_id))
+                                        .map(|(index, before)| {
+                                            let mut after = before.clone();
+                                            after.nodes.retain(|id| *id != node_id);
+                                            if after.nodes.is_empty() {
+                                                Command::RemoveFrame { index, frame: before.clone() }
+                                            } else {
+                                                Command::UpdateFrame { before: before.clone(), after }
+                                            }
+                                        })
+                                        .collect();
+                                    self.execute(Command::Batch(commands));
+                                }
+                                self.show_node_context_menu = false;
+                            }
+
                             ui.separator();
 
                             if ui.button("Delete Node").clicked() {
-                                if let Some(node_id) = self.rightclick_node {
-                                    self.map.remove_node(node_id);
-                                    self.dirty = true;
+                                if let Some(node_id) = self.rightclick_node
+                                    && let Some(command) = Command::remove_node(&self.map, node_id)
+                                {
+                                    self.execute(command);
                                 }
                                 self.show_node_context_menu = false;
                             }
@@ -959,12 +1910,11 @@ impl MindMapApp {
                 });
 
             // Close menu if clicked elsewhere
-            if ctx.input(|i| i.pointer.any_click()) {
-                if let Some(pointer_pos) = ctx.input(|i| i.pointer.interact_pos()) {
-                    if !menu_rect.contains(pointer_pos) {
-                        self.show_node_context_menu = false;
-                    }
-                }
+            if ctx.input(|i| i.pointer.any_click())
+                && let Some(pointer_pos) = ctx.input(|i| i.pointer.interact_pos())
+                && !menu_rect.contains(pointer_pos)
+            {
+                self.show_node_context_menu = false;
             }
         }
     }
@@ -984,17 +1934,18 @@ impl MindMapApp {
 
                             // Option to change edge type
                             if ui.button("Change Edge Type").clicked() {
-                                if let Some(edge_id) = self.rightclick_edge {
-                                    if let Some(edge) = self.map.edges.iter_mut().find(|e| e.id == edge_id) {
-                                        self.selected_edge_type = edge.edge_type.clone();
-                                        self.show_edge_type_menu = true;
-                                    }
+                                if let Some(edge_id) = self.rightclick_edge
+                                    && let Some(edge) = self.map.edges.iter().find(|e| e.id == edge_id)
+                                {
+                                    self.selected_edge_type = edge.edge_type;
+                                    self.show_edge_type_menu = true;
                                 }
                                 self.show_edge_context_menu = false;
                             }
                             
                             // Option to view annotations
                             if ui.button("View Annotations").clicked() {
+                                self.annotations_frame_id = None;
                                 self.show_annotations_panel = true;
                                 self.show_edge_context_menu = false;
                             }
@@ -1002,6 +1953,7 @@ impl MindMapApp {
                             // Option to add annotation
                             if ui.button("Add Annotation").clicked() {
                                 if let Some(edge_id) = self.rightclick_edge {
+                                    self.annotations_frame_id = None;
                                     self.edit_annotation = EditableAnnotation::default();
                                     self.show_add_annotation_dialog = true;
                                     self.rightclick_edge = Some(edge_id);
@@ -1009,6 +1961,16 @@ impl MindMapApp {
                                 self.show_edge_context_menu = false;
                             }
 
+                            // Option to label the edge
+                            if ui.button("Edit Label").clicked() {
+                                if let Some(edge) = self.rightclick_edge.and_then(|id| self.map.edges.iter().find(|e| e.id == id)) {
+                                    self.edit_edge_label = edge.label.clone().unwrap_or_default();
+                                    self.edge_label_id = Some(edge.id);
+                                    self.show_edge_label_dialog = true;
+                                }
+                                self.show_edge_context_menu = false;
+                            }
+
                             if ui.button("Change Color").clicked() {
                                 self.edge_color_picker_id = Some(self.rightclick_edge.unwrap());
                                 self.show_edge_color_picker = true;
@@ -1027,9 +1989,10 @@ impl MindMapApp {
 
                             // Option to delete edge
                             if ui.button("Delete Edge").clicked() {
-                                if let Some(edge_id) = self.rightclick_edge {
-                                    self.map.edges.retain(|e| e.id != edge_id);
-                                    self.dirty = true;
+                                if let Some(edge_id) = self.rightclick_edge
+                                    && let Some(command) = Command::remove_edge(&self.map, edge_id)
+                                {
+                                    self.execute(command);
                                 }
                                 self.show_edge_context_menu = false;
                             }
@@ -1037,12 +2000,154 @@ impl MindMapApp {
                 });
 
             // Close menu if clicked elsewhere
-            if ctx.input(|i| i.pointer.any_click()) {
-                if let Some(pointer_pos) = ctx.input(|i| i.pointer.interact_pos()) {
-                    if !menu_rect.contains(pointer_pos) {
-                        self.show_edge_context_menu = false;
-                    }
-                }
+            if ctx.input(|i| i.pointer.any_click())
+                && let Some(pointer_pos) = ctx.input(|i| i.pointer.interact_pos())
+                && !menu_rect.contains(pointer_pos)
+            {
+                self.show_edge_context_menu = false;
+            }
+        }
+    }
+
+    fn show_frame_context_menu(&mut self, ctx: &egui::Context) {
+        if self.show_frame_context_menu {
+            let menu_rect = egui::Rect::from_min_size(self.frame_context_menu_pos, egui::vec2(150.0, 100.0));
+            let Some(frame) = self.rightclick_frame.and_then(|id| self.map.frames.iter().find(|f| f.id == id)).cloned() else {
+                self.show_frame_context_menu = false;
+                return;
+            };
+
+            egui::Area::new(Id::from("frame_context_menu"))
+                .fixed_pos(self.frame_context_menu_pos)
+                .order(egui::Order::Tooltip)
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style())
+                        .fill(egui::Color32::from_hex("#30313c").unwrap())
+                        .show(ui, |ui| {
+                            ui.set_min_width(150.0);
+
+                            if ui.button("Edit Frame").clicked() {
+                                self.open_frame_dialog(Some(frame.id), Vec::new());
+                                self.show_frame_context_menu = false;
+                            }
+
+                            if ui.button(if frame.collapsed { "Expand" } else { "Collapse" }).clicked() {
+                                self.update_frame(frame.id, |f| f.collapsed = !f.collapsed);
+                                self.show_frame_context_menu = false;
+                            }
+
+                            if ui.button("View Annotations").clicked() {
+                                self.annotations_frame_id = Some(frame.id);
+                                self.annotations_node_id = None;
+                                self.rightclick_edge = None;
+                                self.show_annotations_panel = true;
+                                self.show_frame_context_menu = false;
+                            }
+
+                            if ui.button("Add Annotation").clicked() {
+                                self.annotations_frame_id = Some(frame.id);
+                                self.annotations_node_id = None;
+                                self.rightclick_edge = None;
+                                self.edit_annotation = EditableAnnotation::default();
+                                self.show_add_annotation_dialog = true;
+                                self.show_frame_context_menu = false;
+                            }
+
+                            let new_members: Vec<Uuid> = self.selected_nodes.iter().copied().filter(|id| !frame.nodes.contains(id)).collect();
+                            if ui.add_enabled(!new_members.is_empty(), egui::Button::new("Add Selected Nodes")).clicked() {
+                                self.update_frame(frame.id, |f| f.nodes.extend(new_members));
+                                self.show_frame_context_menu = false;
+                            }
+
+                            ui.separator();
+
+                            // The frame's nodes stay on the map
+                            if ui.button("Delete Frame").clicked() {
+                                if let Some(command) = Command::remove_frame(&self.map, frame.id) {
+                                    self.execute(command);
+                                }
+                                self.show_frame_context_menu = false;
+                            }
+                        });
+                });
+
+            // Close menu if clicked elsewhere
+            if ctx.input(|i| i.pointer.any_click())
+                && let Some(pointer_pos) = ctx.input(|i| i.pointer.interact_pos())
+                && !menu_rect.contains(pointer_pos)
+            {
+                self.show_frame_context_menu = false;
+            }
+        }
+    }
+
+    /// Edit the label and color of `frame_id`, or with None create a frame
+    /// around `nodes`.
+    fn open_frame_dialog(&mut self, frame_id: Option<Uuid>, nodes: Vec<Uuid>) {
+        let frame = frame_id.and_then(|id| self.map.frames.iter().find(|f| f.id == id));
+        self.edit_frame_label = frame.map_or("Group".to_string(), |f| f.label.clone());
+        self.edit_frame_color = color32(frame.map_or([120, 170, 220, 255], |f| f.color));
+        self.frame_dialog_id = frame_id;
+        self.frame_dialog_nodes = nodes;
+        self.show_frame_dialog = true;
+    }
+
+    fn show_frame_dialog(&mut self, ctx: &egui::Context) {
+        if self.show_frame_dialog {
+            let title = if self.frame_dialog_id.is_some() { "Edit Frame" } else { "New Frame" };
+            egui::Window::new(title)
+                .frame(get_popup_frame())
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    egui::Grid::new("frame_grid")
+                        .num_columns(2)
+                        .spacing([40.0, 4.0])
+                        .show(ui, |ui| {
+                            ui.label("Label:");
+                            ui.text_edit_singleline(&mut self.edit_frame_label);
+                            ui.end_row();
+
+                            ui.label("Color:");
+                            ui.color_edit_button_srgba(&mut self.edit_frame_color);
+                            ui.end_row();
+                        });
+
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        let save_text = if self.frame_dialog_id.is_some() { "Update" } else { "Create" };
+                        if ui.button(save_text).clicked() {
+                            self.save_frame();
+                            self.show_frame_dialog = false;
+                        }
+
+                        if ui.button("Cancel").clicked() {
+                            self.show_frame_dialog = false;
+                        }
+                    });
+                });
+        }
+    }
+
+    fn save_frame(&mut self) {
+        let label = self.edit_frame_label.trim().to_string();
+        let color = self.edit_frame_color.to_array();
+        match self.frame_dialog_id {
+            Some(frame_id) => self.update_frame(frame_id, |frame| {
+                frame.label = label;
+                frame.color = color;
+            }),
+            None => {
+                let frame = Frame {
+                    id: Uuid::new_v4(),
+                    label,
+                    color,
+                    nodes: std::mem::take(&mut self.frame_dialog_nodes),
+                    collapsed: false,
+                    annotations: Vec::new(),
+                };
+                self.execute(Command::AddFrame(frame));
             }
         }
     }
@@ -1127,6 +2232,45 @@ impl MindMapApp {
                         }
                     });
             }
+            if let Some(frame_id) = self.annotations_frame_id {
+                egui::Window::new("Annotations")
+                    .frame(get_popup_frame())
+                    .collapsible(false)
+                    .resizable(true)
+                    .default_width(400.0)
+                    .default_height(600.0)
+                    .show(ctx, |ui| {
+                        // Find the frame and clone the data we need
+                        let frame_data = self.map.frames.iter()
+                            .find(|f| f.id == frame_id)
+                            .map(|f| (f.label.clone(), f.annotations.clone()));
+
+                        if let Some((label, annotations)) = frame_data {
+                            ui.heading(format!("Annotations for frame: {}", label));
+                            ui.separator();
+
+                            // Add new annotation button
+                            if ui.button("➕ Add New Annotation").clicked() {
+                                self.edit_annotation = EditableAnnotation::default();
+                                self.show_add_annotation_dialog = true;
+                            }
+
+                            ui.separator();
+
+                            // Show existing annotations
+                            self.show_existing_annotations(annotations, ui, frame_id);
+
+                            ui.horizontal(|ui| {
+                                if ui.button("Close").clicked() {
+                                    self.show_annotations_panel = false;
+                                    self.annotations_frame_id = None;
+                                }
+                            });
+                        } else {
+                            ui.label("Frame not found");
+                        }
+                    });
+            }
         }
     }
 
@@ -1190,14 +2334,9 @@ impl MindMapApp {
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     if ui.small_button("🗑").on_hover_text("Delete").clicked() {
                         // Remove annotation
-                        if let Some(node) = self.map.nodes.iter_mut().find(|n| n.id == id) {
-                            node.annotations.retain(|a| a.id != annotation.id);
-                            self.dirty = true;
-                        }
-                        if let Some(edge) = self.map.edges.iter_mut().find(|n| n.id == id) {
-                            edge.annotations.retain(|a| a.id != annotation.id);
-                            self.dirty = true;
-                        }
+                        self.update_node(id, |node| node.annotations.retain(|a| a.id != annotation.id));
+                        self.update_edge(id, |edge| edge.annotations.retain(|a| a.id != annotation.id));
+                        self.update_frame(id, |frame| frame.annotations.retain(|a| a.id != annotation.id));
                     }
 
                     if ui.small_button("✏").on_hover_text("Edit").clicked() {
@@ -1233,11 +2372,8 @@ impl MindMapApp {
 
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                     if ui.small_button("🗑").on_hover_text("Delete").clicked() {
-                        // Remove annotation
-                        if let Some(node) = self.map.nodes.iter_mut().find(|n| n.id == id) {
-                            node.tags.retain(|a| id != tag.id);
-                            self.dirty = true;
-                        }
+                        // Remove tag from the node
+                        self.update_node(id, |node| node.tags.retain(|t| *t != tag.id));
                     }
 
                     if ui.small_button("✏").on_hover_text("Edit").clicked() {
@@ -1337,7 +2473,7 @@ impl MindMapApp {
                         .selected_text(self.edit_annotation.annotation_type.name())
                         .show_ui(ui, |ui| {
                             for tag in &mut self.map.tags {
-                                ui.selectable_value(&mut self.edit_tag.id, tag.id.clone(), tag.name.as_str());
+                                ui.selectable_value(&mut self.edit_tag.id, tag.id, tag.name.as_str());
                             }
                         });
 
@@ -1409,111 +2545,83 @@ impl MindMapApp {
     }
 
     fn save_annotation(&mut self) {
-        if let Some(node_id) = self.annotations_node_id {
-            if let Some(node) = self.map.nodes.iter_mut().find(|n| n.id == node_id) {
-                let page_number = if self.edit_annotation.page_number.trim().is_empty() {
-                    None
-                } else {
-                    self.edit_annotation.page_number.parse().ok()
-                };
-
-                let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string();
-
-                if let Some(edit_id) = self.edit_annotation_id {
-                    // Editing existing annotation
-                    if let Some(annotation) = node.annotations.iter_mut().find(|a| a.id == edit_id) {
-                        annotation.title = self.edit_annotation.title.clone();
-                        annotation.content = self.edit_annotation.content.clone();
-                        annotation.annotation_type = self.edit_annotation.annotation_type.clone();
-                        annotation.page_number = page_number;
-                    }
-                    self.edit_annotation_id = None;
-                } else {
-                    // Adding new annotation
-                    let annotation = Annotation {
-                        id: Uuid::new_v4(),
-                        annotation_type: self.edit_annotation.annotation_type.clone(),
-                        title: self.edit_annotation.title.clone(),
-                        content: self.edit_annotation.content.clone(),
-                        created_at: now,
-                        page_number,
-                    };
-                    node.annotations.push(annotation);
+        let page_number: Option<u32> = if self.edit_annotation.page_number.trim().is_empty() {
+            None
+        } else {
+            self.edit_annotation.page_number.parse().ok()
+        };
+        let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string();
+        let edit = self.edit_annotation.clone();
+        let edit_id = self.edit_annotation_id.take();
+
+        // Apply the edit to an annotation list; shared by nodes and edges
+        let apply = |annotations: &mut Vec<Annotation>| {
+            if let Some(edit_id) = edit_id {
+                // Editing existing annotation
+                if let Some(annotation) = annotations.iter_mut().find(|a| a.id == edit_id) {
+                    annotation.title = edit.title.clone();
+                    annotation.content = edit.content.clone();
+                    annotation.annotation_type = edit.annotation_type.clone();
+                    annotation.page_number = page_number;
                 }
-
-                self.dirty = true;
+            } else {
+                // Adding new annotation
+                annotations.push(Annotation {
+                    id: Uuid::new_v4(),
+                    annotation_type: edit.annotation_type.clone(),
+                    title: edit.title.clone(),
+                    content: edit.content.clone(),
+                    created_at: now.clone(),
+                    page_number,
+                });
             }
+        };
+
+        if let Some(node_id) = self.annotations_node_id {
+            self.update_node(node_id, |node| apply(&mut node.annotations));
         }
 
         if let Some(edge_id) = self.rightclick_edge {
-            if let Some(edge) = self.map.edges.iter_mut().find(|e| e.id == edge_id) {
-                let page_number = if self.edit_annotation.page_number.trim().is_empty() {
-                    None
-                } else {
-                    self.edit_annotation.page_number.parse().ok()
-                };
-
-                let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC").to_string();
+            self.update_edge(edge_id, |edge| apply(&mut edge.annotations));
+        }
 
-                if let Some(edit_id) = self.edit_annotation_id {
-                    // Editing existing annotation
-                    if let Some(annotation) = edge.annotations.iter_mut().find(|a| a.id == edit_id) {
-                        annotation.title = self.edit_annotation.title.clone();
-                        annotation.content = self.edit_annotation.content.clone();
-                        annotation.annotation_type = self.edit_annotation.annotation_type.clone();
-                        annotation.page_number = page_number;
-                    }
-                    self.edit_annotation_id = None;
-                }else {
-                    // Add new annotation
-                    let annotation = Annotation {
-                        id: Uuid::new_v4(),
-                        annotation_type: self.edit_annotation.annotation_type.clone(),
-                        title: self.edit_annotation.title.clone(),
-                        content: self.edit_annotation.content.clone(),
-                        created_at: now,
-                        page_number,
-                    };
-                    edge.annotations.push(annotation);
-                }
-                self.dirty = true;
-            }
+        if let Some(frame_id) = self.annotations_frame_id {
+            self.update_frame(frame_id, |frame| apply(&mut frame.annotations));
         }
     }
 
     fn add_tag(&mut self) {
         if let Some(node_id) = self.tags_node_id {
-            if let Some(node) = self.map.nodes.iter_mut().find(|n| n.id == node_id) {
-                if !node.tags.contains(&self.edit_tag.id) {
-                    node.tags.push(self.edit_tag.id);
-                    self.dirty = true;
-                }
+            let tag_id = self.edit_tag.id;
+            if self.map.nodes.iter().any(|n| n.id == node_id && !n.tags.contains(&tag_id)) {
+                self.update_node(node_id, |node| node.tags.push(tag_id));
             }
         }
     }
     fn create_tag(&mut self, is_editing: bool) {
+        let color = self.edit_tag.color;
         if is_editing {
-            if let Some(tag_id) = self.edit_tag_id {
-                if let Some(tag) = self.map.tags.iter_mut().find(|t| t.id == tag_id) {
-                    tag.name = self.edit_tag.name.clone();
-                    let color = self.edit_tag.color;
-                    tag.color = [color.r(), color.g(), color.b(), color.a()];
-                }
+            if let Some(tag_id) = self.edit_tag_id && let Some(before) = self.map.tags.iter().find(|t| t.id == tag_id).cloned() {
+                let after = Tag {
+                    name: self.edit_tag.name.clone(),
+                    color: [color.r(), color.g(), color.b(), color.a()],
+                    ..before.clone()
+                };
+                self.execute(Command::UpdateTag { before, after });
             }
         } else {
-            let color = self.edit_tag.color;
             let new_tag = Tag {
                 id: Uuid::new_v4(),
                 name: self.edit_tag.name.clone(),
                 color: [color.r(), color.g(), color.b(), color.a()],
             };
-            self.map.tags.push(new_tag.clone());
-            if let Some(node_id) = self.tags_node_id {
-                if let Some(node) = self.map.nodes.iter_mut().find(|n| n.id == node_id) {
-                    node.tags.push(new_tag.id);
-                    self.dirty = true;
-                }
+            let mut commands = vec![Command::AddTag(new_tag.clone())];
+            if let Some(node_id) = self.tags_node_id && let Some(before) = self.map.nodes.iter().find(|n| n.id == node_id).cloned() {
+                let mut after = before.clone();
+                after.tags.push(new_tag.id);
+                commands.push(Command::UpdateNode { before, after });
             }
+            self.execute(Command::Batch(commands));
         }
     }
 
@@ -1529,14 +2637,15 @@ impl MindMapApp {
                         authors: metadata.authors.join(", "),
                         keywords: metadata.keywords.join(", "),
                         date: metadata.date.clone(),
+                        doi: metadata.doi.clone().unwrap_or_default(),
+                        arxiv: metadata.arxiv.clone().unwrap_or_default(),
                     };
                 } else {
                     // Initialize with node title if no metadata exists
                     self.edit_metadata = EditableMetadata {
                         title: node.title.clone(),
                         authors: String::new(),
-                        keywords: String::new(),
-                        date: String::new(),
+                        ..Default::default()
                     };
                 }
             }
@@ -1574,6 +2683,14 @@ impl MindMapApp {
                             ui.label("Date:");
                             ui.text_edit_singleline(&mut self.edit_metadata.date);
                             ui.end_row();
+
+                            ui.label("DOI:");
+                            ui.text_edit_singleline(&mut self.edit_metadata.doi);
+                            ui.end_row();
+
+                            ui.label("arXiv ID:");
+                            ui.text_edit_singleline(&mut self.edit_metadata.arxiv);
+                            ui.end_row();
                         });
 
                     ui.separator();
@@ -1594,34 +2711,42 @@ impl MindMapApp {
 
     fn save_edited_metadata(&mut self) {
         if let Some(node_id) = self.edit_node_id {
-            if let Some(node) = self.map.nodes.iter_mut().find(|n| n.id == node_id) {
-                // Update node title
-                node.title = self.edit_metadata.title.clone();
-
-                // Parse and update metadata
-                let authors: Vec<String> = self.edit_metadata.authors
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
-
-                let keywords: Vec<String> = self.edit_metadata.keywords
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
-
-                // Create or update metadata
-                node.metadata = Some(Metadata {
-                    title: self.edit_metadata.title.clone(),
-                    authors,
-                    keywords,
-                    date: self.edit_metadata.date.clone(),
-                    path: "".to_string(),
-                });
+            // Parse and update metadata
+            let authors: Vec<String> = self.edit_metadata.authors
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+
+            let keywords: Vec<String> = self.edit_metadata.keywords
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+
+            // Fields that are not editable in the dialog are carried over
+            let existing = self.map.nodes.iter()
+                .find(|n| n.id == node_id)
+                .and_then(|n| n.metadata.clone())
+                .unwrap_or_default();
+
+            let title = self.edit_metadata.title.clone();
+            let metadata = Metadata {
+                title: title.clone(),
+                authors,
+                keywords,
+                date: self.edit_metadata.date.clone(),
+                // Pasted links are reduced to the bare identifier
+                doi: identifier(&self.edit_metadata.doi, identifiers::find_doi),
+                arxiv: identifier(&self.edit_metadata.arxiv, |id| identifiers::find_arxiv(&format!("arXiv:{}", id))),
+                ..existing
+            };
 
-                self.dirty = true;
-            }
+            self.update_node(node_id, |node| {
+                // Update node title and create or update metadata
+                node.title = title;
+                node.metadata = Some(metadata);
+            });
         }
         self.edit_node_id = None;
     }
@@ -1629,7 +2754,9 @@ impl MindMapApp {
     fn finalize_edge(&mut self) {
         if let (Some(from), Some(to)) = (self.pending_edge_from, self.pending_edge_to) {
             self.map.add_edge(from, to);
-            self.map.edges.last_mut().unwrap().edge_type = self.selected_edge_type.clone();
+            let edge = self.map.edges.last_mut().unwrap();
+            edge.edge_type = self.selected_edge_type;
+            self.history.record(Command::AddEdge(edge.clone()));
             self.dirty = true;
         }
         self.show_edge_type_menu = false;
@@ -1638,50 +2765,822 @@ impl MindMapApp {
     }
 
     fn show_edge_type_menu(&mut self, ctx: &egui::Context) {
-        if self.show_edge_type_menu {
-            if let Some(menu_pos) = self.edge_type_menu_pos {
-                egui::Window::new("Select Edge Type")
-                    .collapsible(false)
-                    .resizable(false)
-                    .fixed_pos(menu_pos) // Use the stored position
-                    .show(ctx, |ui| {
-                        ui.label("Select edge type:");
-
-                        if ui.button("Normal").clicked() {
-                            self.selected_edge_type = EdgeType::Normal;
-                            if let Some(edge_id) = self.rightclick_edge {
-                                if let Some(edge) = self.map.edges.iter_mut().find(|e| e.id == edge_id) {
-                                    edge.edge_type = self.selected_edge_type.clone();
-                                    self.dirty = true;
-                                    self.rightclick_edge = None;
+        if self.show_edge_type_menu && let Some(menu_pos) = self.edge_type_menu_pos {
+            egui::Window::new("Select Edge Type")
+                .collapsible(false)
+                .resizable(false)
+                .fixed_pos(menu_pos) // Use the stored position
+                .show(ctx, |ui| {
+                    ui.label("Select edge type:");
+
+                    let mut chosen = None;
+                    for edge_type in &self.map.edge_types {
+                        let text = egui::RichText::new(&edge_type.name).color(color32(edge_type.color));
+                        if ui.selectable_label(self.selected_edge_type == edge_type.id, text).clicked() {
+                            chosen = Some(edge_type.id);
+                        }
+                    }
+                    if let Some(type_id) = chosen {
+                        self.selected_edge_type = type_id;
+                        if let Some(edge_id) = self.rightclick_edge.take() {
+                            self.update_edge(edge_id, |edge| edge.edge_type = type_id);
+                        } else {
+                            self.finalize_edge();
+                        }
+                        self.show_edge_type_menu = false;
+                    }
+
+                    ui.separator();
+
+                    if ui.button("Manage Edge Types...").clicked() {
+                        self.open_edge_types_dialog();
+                    }
+
+                    if ui.button("Cancel").clicked() {
+                        self.show_edge_type_menu = false;
+                        self.pending_edge_from = None;
+                        self.pending_edge_to = None;
+                        self.edge_type_menu_pos = None; // Reset the position
+                    }
+                });
+        }
+    }
+
+    fn open_edge_types_dialog(&mut self) {
+        self.edit_edge_types = self.map.edge_types.clone();
+        self.show_edge_types_dialog = true;
+    }
+
+    fn show_edge_types_dialog(&mut self, ctx: &egui::Context) {
+        if !self.show_edge_types_dialog {
+            return;
+        }
+
+        let frame = get_popup_frame();
+        let mut open = true;
+        let mut save = false;
+        let mut cancel = false;
+        egui::Window::new("Edge Types")
+            .frame(frame)
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                ui.label("Edge types set the default look of their edges. Deleted types turn their edges into Normal edges.");
+                ui.separator();
+
+                let mut delete = None;
+                egui::Grid::new("edge_types_grid")
+                    .num_columns(7)
+                    .spacing([12.0, 6.0])
+                    .show(ui, |ui| {
+                        for header in ["", "Name", "Color", "Line", "Directed", "Arrowhead", ""] {
+                            ui.label(egui::RichText::new(header).strong());
+                        }
+                        ui.end_row();
+
+                        for (i, edge_type) in self.edit_edge_types.iter_mut().enumerate() {
+                            // Preview of the line and arrowhead
+                            let (response, painter) = ui.allocate_painter(egui::vec2(48.0, 16.0), egui::Sense::hover());
+                            let y = response.rect.center().y;
+                            let (start, end) = (egui::pos2(response.rect.left(), y), egui::pos2(response.rect.right() - 2.0, y));
+                            let stroke = egui::Stroke::new(2.0, color32(edge_type.color));
+                            draw_line(&painter, &[start, end], stroke, edge_type.dash);
+                            if edge_type.directed && edge_type.arrow != ArrowStyle::None {
+                                let points = vec![end, end + egui::vec2(-8.0, -5.0), end + egui::vec2(-8.0, 5.0)];
+                                if edge_type.arrow == ArrowStyle::Filled {
+                                    painter.add(egui::Shape::convex_polygon(points, stroke.color, stroke));
+                                } else {
+                                    painter.line_segment([points[0], points[1]], stroke);
+                                    painter.line_segment([points[0], points[2]], stroke);
                                 }
+                            }
+
+                            ui.add(egui::TextEdit::singleline(&mut edge_type.name).desired_width(140.0));
+
+                            let mut color = color32(edge_type.color);
+                            if ui.color_edit_button_srgba(&mut color).changed() {
+                                edge_type.color = color.to_array();
+                            }
+
+                            egui::ComboBox::from_id_salt(("edge_type_dash", edge_type.id))
+                                .selected_text(edge_type.dash.name())
+                                .show_ui(ui, |ui| {
+                                    for dash in DashStyle::ALL {
+                                        ui.selectable_value(&mut edge_type.dash, dash, dash.name());
+                                    }
+                                });
+
+                            ui.checkbox(&mut edge_type.directed, "");
+
+                            ui.add_enabled_ui(edge_type.directed, |ui| {
+                                egui::ComboBox::from_id_salt(("edge_type_arrow", edge_type.id))
+                                    .selected_text(edge_type.arrow.name())
+                                    .show_ui(ui, |ui| {
+                                        for arrow in ArrowStyle::ALL {
+                                            ui.selectable_value(&mut edge_type.arrow, arrow, arrow.name());
+                                        }
+                                    });
+                            });
+
+                            let count = self.map.edges.iter().filter(|e| e.edge_type == edge_type.id).count();
+                            let hover = if edge_type.is_builtin() {
+                                "Built-in types can't be deleted".to_string()
                             } else {
-                                self.finalize_edge();
+                                format!("Delete, {} edges become Normal edges", count)
+                            };
+                            if ui.add_enabled(!edge_type.is_builtin(), egui::Button::new("🗑").small())
+                                .on_hover_text(hover)
+                                .on_disabled_hover_text("Built-in types can't be deleted")
+                                .clicked()
+                            {
+                                delete = Some(i);
                             }
-                            self.show_edge_type_menu = false;
+                            ui.end_row();
                         }
+                    });
+                if let Some(i) = delete {
+                    self.edit_edge_types.remove(i);
+                }
 
-                        if ui.button("References").clicked() {
-                            self.selected_edge_type = EdgeType::References;
-                            if let Some(edge_id) = self.rightclick_edge {
-                                if let Some(edge) = self.map.edges.iter_mut().find(|e| e.id == edge_id) {
-                                    edge.edge_type = self.selected_edge_type.clone();
-                                    self.dirty = true;
-                                    self.rightclick_edge = None;
+                if ui.button("➕ Add Edge Type").clicked() {
+                    self.edit_edge_types.push(EdgeType {
+                        id: Uuid::new_v4(),
+                        name: String::new(),
+                        color: [120, 170, 255, 255],
+                        dash: DashStyle::Solid,
+                        arrow: ArrowStyle::Open,
+                        directed: true,
+                    });
+                }
+
+                ui.separator();
+
+                let named = self.edit_edge_types.iter().all(|t| !t.name.trim().is_empty());
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(named, egui::Button::new("Save")).on_disabled_hover_text("Every edge type needs a name").clicked() {
+                        save = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        cancel = true;
+                    }
+                });
+            });
+
+        if save {
+            self.save_edge_types();
+        }
+        if save || cancel || !open {
+            self.show_edge_types_dialog = false;
+            self.edit_edge_types.clear();
+        }
+    }
+
+    // Apply the edited edge types as one undo step
+    fn save_edge_types(&mut self) {
+        let mut scratch = self.map.clone();
+        let mut commands = Vec::new();
+
+        let removed: Vec<Uuid> = self.map.edge_types.iter()
+            .filter(|t| !self.edit_edge_types.iter().any(|edited| edited.id == t.id))
+            .map(|t| t.id)
+            .collect();
+        for type_id in removed {
+            if let Some(command) = Command::remove_edge_type(&scratch, type_id) {
+                command.apply(&mut scratch);
+                commands.push(command);
+            }
+        }
+
+        for edited in &self.edit_edge_types {
+            let mut edited = edited.clone();
+            edited.name = edited.name.trim().to_string();
+            let command = match scratch.edge_type(edited.id) {
+                Some(before) if *before == edited => continue,
+                Some(before) => Command::UpdateEdgeType { before: before.clone(), after: edited },
+                None => Command::AddEdgeType(edited),
+            };
+            command.apply(&mut scratch);
+            commands.push(command);
+        }
+
+        if !commands.is_empty() {
+            self.execute(Command::Batch(commands));
+        }
+        if self.map.edge_type(self.selected_edge_type).is_none() {
+            self.selected_edge_type = EdgeType::NORMAL;
+        }
+    }
+
+    fn start_indexing(&mut self) {
+        if let Some(project_dir) = &self.current_file {
+            self.indexer = Some((project_dir.clone(), spawn_indexer(project_dir.clone())));
+        }
+    }
+
+    fn poll_indexer(&mut self, ctx: &egui::Context) {
+        if let Some((project_dir, receiver)) = &self.indexer {
+            // results for a project that is no longer open are dropped
+            let current = self.current_file.as_ref() == Some(project_dir);
+            match receiver.try_recv() {
+                Ok(result) => {
+                    match result {
+                        Ok(index) if current => {
+                            self.search_index = Some(index);
+                            self.run_search();
+                        }
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Failed to index PDFs: {}", e),
+                    }
+                    self.indexer = None;
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => {
+                    ctx.request_repaint_after(std::time::Duration::from_millis(250));
+                }
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    self.indexer = None;
+                }
+            }
+        }
+    }
+
+    /// Read the bibliography of a PDF node in the background; the cited nodes
+    /// it finds are offered in the link review window. `requested` reports
+    /// failures and empty results, which are expected for automatic scans.
+    fn scan_citations(&mut self, node_id: Uuid, requested: bool) {
+        let path = self.map.nodes.iter()
+            .find(|n| n.id == node_id)
+            .and_then(|n| n.path.clone());
+        if let Some(path) = path {
+            self.citation_scans.push(CitationScan { node_id, requested, receiver: citations::spawn_extractor(path.into()) });
+        }
+    }
+
+    fn poll_citation_scans(&mut self, ctx: &egui::Context) {
+        let mut finished = Vec::new();
+        self.citation_scans.retain(|scan| match scan.receiver.try_recv() {
+            Ok(result) => {
+                finished.push((scan.node_id, scan.requested, result));
+                false
+            }
+            Err(std::sync::mpsc::TryRecvError::Empty) => true,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => false,
+        });
+        if !self.citation_scans.is_empty() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(250));
+        }
+
+        for (node_id, requested, result) in finished {
+            let proposals = match result {
+                Ok(references) => citations::propose_links(&self.map, node_id, &references),
+                Err(e) => {
+                    eprintln!("Failed to read references: {}", e);
+                    if requested {
+                        self.notice = Some(("No References Found".to_string(), format!("Could not read the reference list of this PDF: {}", e)));
+                    }
+                    continue;
+                }
+            };
+
+            if proposals.is_empty() && requested {
+                self.notice = Some(("No References Found".to_string(), "None of the papers in this PDF's reference list are on the map.".to_string()));
+            }
+            for proposal in proposals {
+                let known = self.link_proposals.iter().any(|(p, _)| p.from == proposal.from && p.to == proposal.to);
+                if !known {
+                    self.link_proposals.push((proposal, true));
+                }
+            }
+        }
+    }
+
+    fn show_link_review(&mut self, ctx: &egui::Context) {
+        if self.link_proposals.is_empty() {
+            return;
+        }
+
+        let frame = get_popup_frame();
+        let mut open = true;
+        let mut add = false;
+        egui::Window::new("Review Citation Links")
+            .frame(frame)
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(460.0)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                ui.label("These papers appear in the reference lists of newly added PDFs. Ticked links are added as References edges.");
+                ui.separator();
+
+                egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                    for (proposal, accepted) in &mut self.link_proposals {
+                        let title = |id: Uuid| self.map.nodes.iter().find(|n| n.id == id).map_or("?", |n| n.title.as_str());
+                        ui.checkbox(accepted, format!("{} → {}", title(proposal.from), title(proposal.to)));
+                        ui.label(egui::RichText::new(format!("{:.0}% match: {}", proposal.score * 100.0, proposal.reference))
+                            .small()
+                            .color(egui::Color32::DARK_GRAY));
+                        ui.add_space(4.0);
+                    }
+                });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    let count = self.link_proposals.iter().filter(|(_, accepted)| *accepted).count();
+                    if ui.add_enabled(count > 0, egui::Button::new(format!("Add {} Links", count))).clicked() {
+                        add = true;
+                    }
+                    if ui.button("Select All").clicked() {
+                        self.link_proposals.iter_mut().for_each(|(_, accepted)| *accepted = true);
+                    }
+                    if ui.button("Select None").clicked() {
+                        self.link_proposals.iter_mut().for_each(|(_, accepted)| *accepted = false);
+                    }
+                });
+            });
+
+        if add {
+            // Nodes may have been deleted while the window was open
+            let commands: Vec<Command> = self.link_proposals.drain(..)
+                .filter(|(proposal, accepted)| {
+                    *accepted && [proposal.from, proposal.to].iter().all(|id| self.map.nodes.iter().any(|n| n.id == *id))
+                })
+                .map(|(proposal, _)| Command::AddEdge(Edge {
+                    id: Uuid::new_v4(),
+                    from: proposal.from,
+                    to: proposal.to,
+                    edge_type: EdgeType::REFERENCES,
+                    annotations: Vec::new(),
+                    color: None,
+                    label: None,
+                }))
+                .collect();
+            if !commands.is_empty() {
+                self.execute(Command::Batch(commands));
+            }
+        } else if !open {
+            self.link_proposals.clear();
+        }
+    }
+
+    fn find_duplicates(&mut self) {
+        self.duplicate_groups = duplicates::find_duplicates(&self.map)
+            .into_iter()
+            .map(|group| {
+                let survivor = duplicates::default_survivor(&self.map, &group);
+                (group, survivor)
+            })
+            .collect();
+    }
+
+    fn show_duplicates_window(&mut self, ctx: &egui::Context) {
+        if !self.show_duplicates {
+            return;
+        }
+
+        let frame = get_popup_frame();
+        let mut open = true;
+        let mut merge = None;
+        let mut dismiss = None;
+        let mut focus_node = None;
+        let mut rescan = false;
+        egui::Window::new("Duplicates")
+            .frame(frame)
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(420.0)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} groups of possible duplicates", self.duplicate_groups.len()));
+                    if ui.button("Scan Again").clicked() {
+                        rescan = true;
+                    }
+                });
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    if self.duplicate_groups.is_empty() {
+                        ui.label("No duplicates found.");
+                    }
+                    for (i, (group, survivor)) in self.duplicate_groups.iter_mut().enumerate() {
+                        ui.label(egui::RichText::new(group.evidence.name()).strong());
+                        for node_id in &group.nodes {
+                            let Some(node) = self.map.nodes.iter().find(|n| n.id == *node_id) else { continue };
+                            ui.horizontal(|ui| {
+                                ui.radio_value(survivor, *node_id, node.title.as_str())
+                                    .on_hover_text("Keep this node and merge the others into it");
+                                let details = format!(
+                                    "{} annotations, {} tags{}",
+                                    node.annotations.len(),
+                                    node.tags.len(),
+                                    if node.path.is_some() { ", PDF" } else { "" }
+                                );
+                                ui.label(egui::RichText::new(details).small().color(egui::Color32::DARK_GRAY));
+                                if ui.small_button("Show").clicked() {
+                                    focus_node = Some(*node_id);
                                 }
-                            } else {
-                                self.finalize_edge();
+                            });
+                        }
+                        ui.horizontal(|ui| {
+                            if ui.button("Merge").clicked() {
+                                merge = Some(i);
+                            }
+                            if ui.button("Not Duplicates").clicked() {
+                                dismiss = Some(i);
+                            }
+                        });
+                        ui.separator();
+                    }
+                });
+            });
+
+        if let Some(i) = merge {
+            let (group, survivor) = self.duplicate_groups.remove(i);
+            if let Some(command) = duplicates::merge_command(&self.map, survivor, &group.nodes) {
+                self.execute(command);
+                self.forget_missing_selection();
+            }
+        }
+        if let Some(i) = dismiss {
+            self.duplicate_groups.remove(i);
+        }
+        if let Some(node_id) = focus_node {
+            self.center_on_node(node_id);
+        }
+        if rescan {
+            self.find_duplicates();
+        }
+        if !open {
+            self.show_duplicates = false;
+            self.duplicate_groups.clear();
+        }
+    }
+
+    fn run_search(&mut self) {
+        self.search_results = match &self.search_index {
+            Some(index) => index.search(&self.search_query),
+            None => Vec::new(),
+        };
+    }
+
+    fn show_search_panel(&mut self, ctx: &egui::Context) {
+        if self.show_search_panel {
+            let frame = get_popup_frame();
+            let mut open = true;
+            egui::Window::new("Search PDFs")
+                .frame(frame)
+                .open(&mut open)
+                .collapsible(false)
+                .resizable(true)
+                .default_width(400.0)
+                .default_height(500.0)
+                .show(ctx, |ui| {
+                    let response = ui.text_edit_singleline(&mut self.search_query);
+                    if response.changed() {
+                        self.run_search();
+                    }
+
+                    if self.indexer.is_some() {
+                        ui.horizontal(|ui| {
+                            ui.spinner();
+                            ui.label("Indexing PDFs...");
+                        });
+                    } else if let Some(index) = &self.search_index {
+                        ui.label(egui::RichText::new(format!("{} PDFs indexed", index.document_count())).small().color(egui::Color32::DARK_GRAY));
+                    } else {
+                        ui.label("Save or open a project to search its PDFs.");
+                    }
+
+                    ui.separator();
+
+                    let mut focus_node = None;
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        for hit in &self.search_results {
+                            // Only list hits that belong to a node on the map
+                            let node = self.map.nodes.iter().find(|n| {
+                                n.path.as_ref()
+                                    .and_then(|p| std::path::Path::new(p).file_name())
+                                    .is_some_and(|f| f.to_string_lossy() == hit.file)
+                            });
+
+                            if let Some(node) = node {
+                                let card = ui.vertical(|ui| {
+                                    ui.horizontal(|ui| {
+                                        ui.label(egui::RichText::new(&node.title).strong().color(egui::Color32::WHITE));
+                                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                            ui.label(egui::RichText::new(format!("p.{}", hit.page)).strong().color(egui::Color32::DARK_GRAY));
+                                        });
+                                    });
+                                    ui.label(egui::RichText::new(&hit.snippet).color(egui::Color32::LIGHT_GRAY));
+                                }).response.interact(egui::Sense::click());
+
+                                if card.clicked() {
+                                    focus_node = Some(node.id);
+                                }
+                                ui.separator();
                             }
-                            self.show_edge_type_menu = false;
                         }
 
-                        if ui.button("Cancel").clicked() {
-                            self.show_edge_type_menu = false;
-                            self.pending_edge_from = None;
-                            self.pending_edge_to = None;
-                            self.edge_type_menu_pos = None; // Reset the position
+                        if self.search_results.is_empty() && !self.search_query.trim().is_empty() {
+                            ui.label("No matches.");
                         }
                     });
+
+                    if let Some(node_id) = focus_node {
+                        self.center_on_node(node_id);
+                    }
+                });
+            self.show_search_panel = open;
+        }
+    }
+
+    fn import_references(&mut self, format: Format, path: &std::path::Path) {
+        let entries = match format.import_file(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Failed to read {} file: {}", format.name(), e);
+                return;
+            }
+        };
+
+        let pdfs_dir = self.current_file.as_ref().map(|dir| std::path::Path::new(dir).join("pdfs"));
+        let center = self.view_center();
+        let commands = references::import_commands(&self.map, &entries, pdfs_dir.as_deref(), [center.x, center.y]);
+
+        let attached_pdf = commands.iter().any(|c| matches!(c, Command::AddNode(n) if n.path.is_some()));
+        if !commands.is_empty() {
+            self.execute(Command::Batch(commands));
+        }
+        if attached_pdf {
+            self.start_indexing();
+        }
+    }
+
+    fn export_references(&mut self, format: Format, selection_only: bool) {
+        if let Some(path) = FileDialog::new()
+            .add_filter(format.name(), format.extensions())
+            .set_file_name(format!("references.{}", format.extensions()[0]))
+            .save_file()
+        {
+            let node_ids = selection_only.then_some(self.selected_nodes.as_slice());
+            if let Err(e) = format.export_file(&self.map, node_ids, &path) {
+                eprintln!("Failed to export {}: {}", format.name(), e);
+            }
+        }
+    }
+
+    /// Highlight the shortest path between the two selected nodes. With edge
+    /// direction followed, a path in either direction is accepted so the order
+    /// of selection doesn't matter.
+    fn show_shortest_path(&mut self) {
+        let [a, b] = self.selected_nodes[..] else { return };
+        let path = analytics::shortest_path(&self.map, a, b, self.path_directed)
+            .or_else(|| self.path_directed.then(|| analytics::shortest_path(&self.map, b, a, true)).flatten());
+
+        match path {
+            Some(edges) => self.highlighted_path = edges,
+            None => {
+                self.highlighted_path.clear();
+                let message = if self.path_directed {
+                    "The selected nodes are not connected by edges pointing the same way. Untick Follow Edge Direction to ignore the direction of edges."
+                } else {
+                    "The selected nodes are not connected by any chain of edges."
+                };
+                self.notice = Some(("No Path Found".to_string(), message.to_string()));
+            }
+        }
+    }
+
+    // Recompute the metrics while the panel or node scaling uses them, after
+    // the map was edited or the size metric changed
+    fn update_analytics(&mut self) {
+        if !self.show_analytics_panel && self.size_metric.is_none() {
+            self.analytics = None;
+            self.analytics_for = None;
+            if !self.node_scale.is_empty() {
+                self.node_scale.clear();
+                self.spatial_index.synced = None; // node sizes changed
+            }
+            return;
+        }
+        let state = (self.history.generation(), self.size_metric);
+        if self.analytics.is_some() && self.analytics_for == Some(state) {
+            return;
+        }
+        self.analytics_for = Some(state);
+
+        let analytics = Analytics::compute(&self.map);
+        self.node_scale.clear();
+        self.spatial_index.synced = None; // node sizes may change
+        if let Some(metric) = self.size_metric {
+            // The lowest value keeps the normal size, PageRank never reaches zero
+            let values = self.map.nodes.iter().map(|n| analytics.value(metric, n.id));
+            let min = values.clone().fold(f32::INFINITY, f32::min);
+            let max = values.fold(0.0, f32::max);
+            if max > min {
+                for node in &self.map.nodes {
+                    let scale = 1.0 + NODE_SCALE_RANGE * (analytics.value(metric, node.id) - min) / (max - min);
+                    self.node_scale.insert(node.id, scale);
+                }
+            }
+        }
+        self.analytics = Some(analytics);
+    }
+
+    fn show_analytics_panel(&mut self, ctx: &egui::Context) {
+        if !self.show_analytics_panel {
+            return;
+        }
+        let Some(analytics) = &self.analytics else { return };
+
+        let frame = get_popup_frame();
+        let mut open = true;
+        let mut select: Option<Vec<Uuid>> = None;
+        let mut size_metric = self.size_metric;
+        let title = |id: &Uuid| self.map.nodes.iter().find(|n| n.id == *id).map_or("?", |n| n.title.as_str());
+
+        egui::Window::new("Graph Analytics")
+            .frame(frame)
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(true)
+            .default_width(360.0)
+            .default_height(500.0)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Scale nodes by:");
+                    egui::ComboBox::from_id_salt("size_metric")
+                        .selected_text(size_metric.map_or("Nothing", Metric::name))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut size_metric, None, "Nothing");
+                            for metric in Metric::ALL {
+                                ui.selectable_value(&mut size_metric, Some(metric), metric.name());
+                            }
+                        });
+                });
+                ui.label(egui::RichText::new("Degrees and PageRank follow References edges. Click an entry to select its nodes.")
+                    .small()
+                    .color(egui::Color32::DARK_GRAY));
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for metric in Metric::ALL {
+                        let ranking = analytics.ranking(metric);
+                        egui::CollapsingHeader::new(format!("{} ({})", metric.name(), ranking.len()))
+                            .id_salt(metric.name())
+                            .default_open(metric == Metric::PageRank)
+                            .show(ui, |ui| {
+                                if ranking.is_empty() {
+                                    ui.label("No References edges yet.");
+                                }
+                                for (node_id, value) in ranking.iter().take(ANALYTICS_TOP) {
+                                    let value = match metric {
+                                        Metric::PageRank => format!("{:.3}", value),
+                                        _ => format!("{}", value),
+                                    };
+                                    if ui.selectable_label(false, format!("{}  {}", value, title(node_id))).clicked() {
+                                        select = Some(vec![*node_id]);
+                                    }
+                                }
+                            });
+                    }
+
+                    let islands: Vec<&Vec<Uuid>> = analytics.components.iter().filter(|c| c.len() > 1).collect();
+                    egui::CollapsingHeader::new(format!("Connected components ({})", islands.len()))
+                        .show(ui, |ui| {
+                            for (i, component) in islands.iter().enumerate() {
+                                let label = format!("{} nodes, including {}", component.len(), title(&component[0]));
+                                if ui.selectable_label(false, label).on_hover_text(format!("Component {}", i + 1)).clicked() {
+                                    select = Some(component.to_vec());
+                                }
+                            }
+                        });
+
+                    egui::CollapsingHeader::new(format!("Orphan nodes ({})", analytics.orphans.len()))
+                        .show(ui, |ui| {
+                            if !analytics.orphans.is_empty() && ui.button("Select All").clicked() {
+                                select = Some(analytics.orphans.clone());
+                            }
+                            for node_id in &analytics.orphans {
+                                if ui.selectable_label(false, title(node_id)).clicked() {
+                                    select = Some(vec![*node_id]);
+                                }
+                            }
+                        });
+                });
+            });
+
+        self.size_metric = size_metric;
+        match select.as_deref() {
+            Some([node_id]) => self.center_on_node(*node_id),
+            Some(node_ids) => {
+                self.selected_nodes = node_ids.to_vec();
+                self.selected_edges = Vec::new();
+            }
+            None => {}
+        }
+        if !open {
+            self.show_analytics_panel = false;
+        }
+    }
+
+    /// Canvas coordinates of the middle of the visible area.
+    fn view_center(&self) -> Pos2 {
+        ((self.canvas_rect.size() / 2.0 - self.pan) / self.zoom).to_pos2()
+    }
+
+    /// Pan the canvas so that `node_id` sits in the middle of the view and select it.
+    fn center_on_node(&mut self, node_id: Uuid) {
+        if let Some(node) = self.map.nodes.iter().find(|n| n.id == node_id) {
+            self.pan = self.canvas_rect.size() / 2.0 - egui::vec2(node.x, node.y) * self.zoom;
+            self.selected_nodes = vec![node_id];
+            self.selected_edges = Vec::new();
+        }
+    }
+
+    /// Load the project in `project_dir`, replacing the current map. Returns
+    /// false and leaves the current project open when the map can't be read,
+    /// so that a file from a newer version is never overwritten by autosave.
+    fn open_project(&mut self, project_dir: &str) -> bool {
+        match load_map_file(project_dir) {
+            Ok(loaded) => {
+                if let Some(version) = loaded.migrated_from {
+                    self.notice = Some((
+                        "Map Upgraded".to_string(),
+                        format!(
+                            "This map was saved by an older version of RefMap (format version {}) and has been upgraded. \
+                             It will be saved in the new format; the original is kept as map.v{}.json.",
+                            version, version
+                        ),
+                    ));
+                }
+                self.current_file = Some(project_dir.to_string());
+                self.map = loaded.map;
+                self.layout = None;
+                self.citation_scans.clear();
+                self.link_proposals.clear();
+                self.duplicate_groups.clear();
+                self.highlighted_path.clear();
+                self.show_edge_types_dialog = false;
+                self.history.clear();
+                self.selected_nodes.clear();
+                self.selected_edges.clear();
+                self.search_index = None;
+                self.search_results.clear();
+                self.start_indexing();
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to open project: {}", e);
+                self.notice = Some(("Could Not Open Project".to_string(), format!("{}: {}", project_dir, e)));
+                false
+            }
+        }
+    }
+
+    /// Unpack an exported project zip and open it. The zip is extracted into the
+    /// chosen folder when it is empty, otherwise into a new subfolder named
+    /// after the archive.
+    fn import_project(&mut self) {
+        let Some(zip_path) = FileDialog::new().add_filter("ZIP", &["zip"]).pick_file() else { return };
+        let Some(folder) = FileDialog::new().set_title("Choose where to unpack the project").pick_folder() else { return };
+
+        let folder_is_empty = std::fs::read_dir(&folder).map(|mut d| d.next().is_none()).unwrap_or(true);
+        let project_dir = if folder_is_empty {
+            folder
+        } else {
+            folder.join(zip_path.file_stem().unwrap_or_default())
+        };
+        let project_dir = project_dir.to_string_lossy().to_string();
+
+        if let Err(e) = import_project(&zip_path.to_string_lossy(), &project_dir) {
+            eprintln!("Failed to import project: {}", e);
+            self.notice = Some(("Could Not Import Project".to_string(), e.to_string()));
+            return;
+        }
+        if self.open_project(&project_dir) && let Err(e) = save_last_file(&project_dir) {
+            eprintln!("Failed to save last file: {}", e);
+        }
+    }
+
+    fn show_notice(&mut self, ctx: &egui::Context) {
+        if let Some((title, message)) = &self.notice {
+            let mut dismissed = false;
+            egui::Window::new(title.as_str())
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.set_max_width(360.0);
+                    ui.label(message.as_str());
+                    if ui.button("OK").clicked() {
+                        dismissed = true;
+                    }
+                });
+            if dismissed {
+                self.notice = None;
             }
         }
     }
@@ -1705,96 +3604,112 @@ impl MindMapApp {
     }
 
     fn show_node_color_picker(&mut self, ctx: &egui::Context) {
-        if self.show_node_color_picker {
-            if let Some(node_id) = self.node_color_picker_id {
-                egui::Window::new("Change Node Color")
-                    .collapsible(false)
-                    .show(ctx, |ui| {
-                        ui.label("Select a color for this node:");
+        if self.show_node_color_picker && let Some(node_id) = self.node_color_picker_id {
+            egui::Window::new("Change Node Color")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label("Select a color for this node:");
 
-                        // Color editor
-                        let mut color = self.selected_node_color;
-                        if ui.color_edit_button_srgba(&mut color).changed() {
-                            self.selected_node_color = color;
-                        }
+                    // Color editor
+                    let mut color = self.selected_node_color;
+                    if ui.color_edit_button_srgba(&mut color).changed() {
+                        self.selected_node_color = color;
+                    }
 
-                        ui.horizontal(|ui| {
-                            if ui.button("Apply").clicked() {
-                                if let Some(node) = self.map.nodes.iter_mut().find(|n| n.id == node_id) {
-                                    node.color = Some(self.selected_node_color.to_array());
-                                    self.dirty = true;
-                                }
-                                self.show_node_color_picker = false;
-                                self.node_color_picker_id = None;
-                            }
-                            if ui.button("Reset").clicked() {
-                                if let Some(node) = self.map.nodes.iter_mut().find(|e| e.id == node_id) {
-                                    node.color = None;
-                                    self.dirty = true;
-                                }
-                                self.show_node_color_picker = false;
-                                self.edge_color_picker_id = None;
-                            }
-                            if ui.button("Cancel").clicked() {
-                                self.show_node_color_picker = false;
-                                self.node_color_picker_id = None;
-                            }
-                        });
+                    ui.horizontal(|ui| {
+                        if ui.button("Apply").clicked() {
+                            let color = self.selected_node_color.to_array();
+                            self.update_node(node_id, |node| node.color = Some(color));
+                            self.show_node_color_picker = false;
+                            self.node_color_picker_id = None;
+                        }
+                        if ui.button("Reset").clicked() {
+                            self.update_node(node_id, |node| node.color = None);
+                            self.show_node_color_picker = false;
+                            self.edge_color_picker_id = None;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.show_node_color_picker = false;
+                            self.node_color_picker_id = None;
+                        }
                     });
-            }
+                });
         }
     }
 
     fn show_edge_color_picker(&mut self, ctx: &egui::Context) {
-        if self.show_edge_color_picker {
-            if let Some(edge_id) = self.edge_color_picker_id {
-                egui::Window::new("Change Edge Color")
-                    .collapsible(false)
-                    .show(ctx, |ui| {
-                        ui.label("Select a color for this edge:");
+        if self.show_edge_color_picker && let Some(edge_id) = self.edge_color_picker_id {
+            egui::Window::new("Change Edge Color")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label("Select a color for this edge:");
 
-                        // Color editor
-                        let mut color = self.selected_edge_color;
-                        if ui.color_edit_button_srgba(&mut color).changed() {
-                            self.selected_edge_color = color;
+                    // Color editor
+                    let mut color = self.selected_edge_color;
+                    if ui.color_edit_button_srgba(&mut color).changed() {
+                        self.selected_edge_color = color;
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Apply").clicked() {
+                            let color = self.selected_edge_color.to_array();
+                            self.update_edge(edge_id, |edge| edge.color = Some(color));
+                            self.show_edge_color_picker = false;
+                            self.edge_color_picker_id = None;
                         }
+                        if ui.button("Reset").clicked() {
+                            self.update_edge(edge_id, |edge| edge.color = None);
+                            self.show_edge_color_picker = false;
+                            self.edge_color_picker_id = None;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.show_edge_color_picker = false;
+                            self.edge_color_picker_id = None;
+                        }
+                    });
+                });
+        }
+    }
 
-                        ui.horizontal(|ui| {
-                            if ui.button("Apply").clicked() {
-                                if let Some(edge) = self.map.edges.iter_mut().find(|n| n.id == edge_id) {
-                                    edge.color = Some(self.selected_edge_color.to_array());
-                                    self.dirty = true;
-                                }
-                                self.show_edge_color_picker = false;
-                                self.edge_color_picker_id = None;
-                            }
-                            if ui.button("Reset").clicked() {
-                                if let Some(edge) = self.map.edges.iter_mut().find(|e| e.id == edge_id) {
-                                    edge.color = None;
-                                    self.dirty = true;
-                                }
-                                self.show_edge_color_picker = false;
-                                self.edge_color_picker_id = None;
-                            }
-                            if ui.button("Cancel").clicked() {
-                                self.show_edge_color_picker = false;
-                                self.edge_color_picker_id = None;
-                            }
-                        });
+    fn show_edge_label_dialog(&mut self, ctx: &egui::Context) {
+        if self.show_edge_label_dialog && let Some(edge_id) = self.edge_label_id {
+            egui::Window::new("Edge Label")
+                .collapsible(false)
+                .show(ctx, |ui| {
+                    ui.label("Text shown on this edge:");
+                    let response = ui.text_edit_singleline(&mut self.edit_edge_label);
+                    let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+                    ui.horizontal(|ui| {
+                        if ui.button("Apply").clicked() || submitted {
+                            let label = self.edit_edge_label.trim().to_string();
+                            self.update_edge(edge_id, |edge| edge.label = (!label.is_empty()).then_some(label));
+                            self.show_edge_label_dialog = false;
+                            self.edge_label_id = None;
+                        }
+                        if ui.button("Remove").clicked() {
+                            self.update_edge(edge_id, |edge| edge.label = None);
+                            self.show_edge_label_dialog = false;
+                            self.edge_label_id = None;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.show_edge_label_dialog = false;
+                            self.edge_label_id = None;
+                        }
                     });
-            }
+                });
         }
     }
 
     fn save(&mut self) {
         if let Some(project_dir) = FileDialog::new().pick_folder() {
             self.current_file = Some(project_dir.to_str().unwrap().to_string());
-            if let Err(e) = save_last_file(&self.current_file.as_ref().unwrap()) {
+            if let Err(e) = save_last_file(self.current_file.as_ref().unwrap()) {
                 eprintln!("Failed to save last file: {}", e);
             }
 
             // Save the map
-            if let Err(e) = save_map(&self.map, &project_dir.to_str().unwrap()) {
+            if let Err(e) = save_map(&self.map, project_dir.to_str().unwrap()) {
                 eprintln!("Failed to save map: {}", e);
             }
 
@@ -1807,31 +3722,27 @@ impl MindMapApp {
                 let file_name = std::path::Path::new(&pdf_path).file_name().unwrap().to_str().unwrap();
                 let dest_path = pdfs_dir.join(file_name);
                 std::fs::copy(&pdf_path, &dest_path).unwrap();
-                self.map.add_pdf_node(&format!("{}/{}",pdfs_dir.to_str().unwrap(), file_name), 0.0, 0.0).unwrap();
-                self.dirty = true;
+                match self.map.add_pdf_node(&format!("{}/{}",pdfs_dir.to_str().unwrap(), file_name), 0.0, 0.0) {
+                    Ok(id) => {
+                        self.record_added_node(id);
+                        self.scan_citations(id, false);
+                    }
+                    Err(e) => eprintln!("Failed to add PDF node: {}", e),
+                }
             }
+
+            self.start_indexing();
         }
     }
 
-    fn start_edge(&mut self, ctx: &egui::Context, canvas_pos: Pos2){
-        for node in &self.map.nodes {
-            let node_rect = get_node_rect(ctx, node, self.zoom);
-            if node_rect.contains(canvas_pos) {
-                self.connecting_from = Some(node.id);
-                break;
-            }
+    fn start_edge(&mut self, canvas_pos: Pos2){
+        if let Some(node_id) = self.node_at(canvas_pos) {
+            self.connecting_from = Some(node_id);
         }
     }
 
     fn stop_edge(&mut self, ctx: &egui::Context, canvas_pos: Pos2, start_id: Uuid) {
-        let mut found_to_id = None;
-        for node in &self.map.nodes {
-            let node_rect = get_node_rect(ctx, node, self.zoom);
-            if node_rect.contains(canvas_pos) && node.id != start_id {
-                found_to_id = Some(node.id);
-                break;
-            }
-        }
+        let found_to_id = self.nodes_at(canvas_pos).into_iter().find(|id| *id != start_id);
 
         if let Some(to_id) = found_to_id {
             self.pending_edge_from = Some(start_id);
@@ -1841,6 +3752,134 @@ impl MindMapApp {
         }
     }
 
+    /// Start animating a force-directed layout of the whole map, or of the
+    /// selected nodes only.
+    fn start_force_layout(&mut self, ctx: &egui::Context, selection_only: bool) {
+        self.finish_layout();
+        let sizes = self.node_sizes(ctx);
+        let only = selection_only.then_some(self.selected_nodes.as_slice());
+        self.layout = Some(ForceLayout::new(&self.map, &sizes, only));
+    }
+
+    /// Arrange the map in layers following its References edges.
+    fn apply_layered_layout(&mut self, ctx: &egui::Context, cited_above: bool) {
+        self.finish_layout();
+        let sizes = self.node_sizes(ctx);
+        let moves = layout::layered(&self.map, &sizes, cited_above);
+        if !moves.is_empty() {
+            self.execute(Command::MoveNodes(moves));
+        }
+    }
+
+    // Unzoomed size of every node, for the automatic layouts
+    fn node_sizes(&self, ctx: &egui::Context) -> std::collections::HashMap<Uuid, [f32; 2]> {
+        self.map.nodes.iter()
+            .map(|n| {
+                let size = get_node_rect(ctx, n, 1.0).size();
+                (n.id, [size.x, size.y])
+            })
+            .collect()
+    }
+
+    /// Advance a running layout by a few iterations per frame so the nodes
+    /// visibly settle. Grabbing a node stops the layout where it is.
+    fn run_layout(&mut self, ctx: &egui::Context) {
+        if self.dragging_node.is_some() {
+            self.finish_layout();
+        }
+        if let Some(layout) = &mut self.layout {
+            for _ in 0..LAYOUT_STEPS_PER_FRAME {
+                if !layout.step() {
+                    break;
+                }
+            }
+            layout.apply(&mut self.map);
+            self.dirty = true;
+
+            if layout.is_finished() {
+                self.finish_layout();
+            } else {
+                ctx.request_repaint();
+            }
+        }
+    }
+
+    // Stop the running layout, recording everything it moved as one undo step
+    fn finish_layout(&mut self) {
+        if let Some(layout) = self.layout.take() {
+            self.spatial_index.synced = None; // the last steps may have moved nodes
+            let moves = layout.moves();
+            if !moves.is_empty() {
+                self.history.record(Command::MoveNodes(moves));
+            }
+        }
+    }
+
+    /// Apply a command to the map and push it onto the undo stack.
+    fn execute(&mut self, command: Command) {
+        self.finish_layout();
+        self.history.execute(&mut self.map, command);
+        self.dirty = true;
+    }
+
+    /// Edit a node through `f`, recording the change as an undoable step.
+    fn update_node(&mut self, node_id: Uuid, f: impl FnOnce(&mut Node)) {
+        if let Some(before) = self.map.nodes.iter().find(|n| n.id == node_id).cloned() {
+            let mut after = before.clone();
+            f(&mut after);
+            self.execute(Command::UpdateNode { before, after });
+        }
+    }
+
+    /// Edit an edge through `f`, recording the change as an undoable step.
+    fn update_edge(&mut self, edge_id: Uuid, f: impl FnOnce(&mut Edge)) {
+        if let Some(before) = self.map.edges.iter().find(|e| e.id == edge_id).cloned() {
+            let mut after = before.clone();
+            f(&mut after);
+            self.execute(Command::UpdateEdge { before, after });
+        }
+    }
+
+    /// Edit a frame through `f`, recording the change as an undoable step.
+    fn update_frame(&mut self, frame_id: Uuid, f: impl FnOnce(&mut Frame)) {
+        if let Some(before) = self.map.frames.iter().find(|fr| fr.id == frame_id).cloned() {
+            let mut after = before.clone();
+            f(&mut after);
+            self.execute(Command::UpdateFrame { before, after });
+        }
+    }
+
+    /// Record a node that was just pushed onto the map so it can be undone.
+    fn record_added_node(&mut self, node_id: Uuid) {
+        if let Some(node) = self.map.nodes.iter().find(|n| n.id == node_id) {
+            self.history.record(Command::AddNode(node.clone()));
+            self.dirty = true;
+        }
+    }
+
+    fn undo(&mut self) {
+        self.finish_layout();
+        if self.history.undo(&mut self.map) {
+            self.forget_missing_selection();
+            self.dirty = true;
+        }
+    }
+
+    fn redo(&mut self) {
+        self.finish_layout();
+        if self.history.redo(&mut self.map) {
+            self.forget_missing_selection();
+            self.dirty = true;
+        }
+    }
+
+    // Drop selected ids that no longer exist after an undo/redo
+    fn forget_missing_selection(&mut self) {
+        let map = &self.map;
+        self.selected_nodes.retain(|id| map.nodes.iter().any(|n| n.id == *id));
+        self.selected_edges.retain(|id| map.edges.iter().any(|e| e.id == *id));
+    }
+
     fn find_widest_label(ctx: &egui::Context, fields: Vec<(&str, &String)>, zoom: f32) -> f32 {
         ctx.fonts_mut(|f| {
             let bold_font_id = egui::FontId::monospace(14.0 * zoom);
@@ -1885,7 +3924,13 @@ impl Default for MindMapApp {
         let map = MindMap::default();
         let mut app = Self {
             map,
+            history: History::default(),
             dragging_node: None,
+            drag_origin: None,
+            drag_offset: egui::Vec2::ZERO,
+            alignment_guides: Vec::new(),
+            dragging_frame: None,
+            frame_drag_origins: Vec::new(),
             connecting_from: None,
             selected_nodes: Vec::new(),
             selected_edges: Vec::new(),
@@ -1908,14 +3953,25 @@ impl Default for MindMapApp {
             edit_annotation_id: None,
             edit_annotation: EditableAnnotation::default(),
             annotations_node_id: None,
+            annotations_frame_id: None,
             pending_edge_from: None,
             pending_edge_to: None,
             show_edge_type_menu: false,
-            selected_edge_type: EdgeType::Normal,
+            selected_edge_type: EdgeType::NORMAL,
             edge_type_menu_pos: None,
+            show_edge_types_dialog: false,
+            edit_edge_types: Vec::new(),
             rightclick_edge: None,
             show_edge_context_menu: false,
             edge_context_menu_pos: egui::pos2(0.0, 0.0),
+            rightclick_frame: None,
+            show_frame_context_menu: false,
+            frame_context_menu_pos: egui::pos2(0.0, 0.0),
+            show_frame_dialog: false,
+            frame_dialog_id: None,
+            frame_dialog_nodes: Vec::new(),
+            edit_frame_label: String::new(),
+            edit_frame_color: egui::Color32::WHITE,
             show_create_project_prompt: false,
             pending_pdf_path: None,
             show_node_color_picker: false,
@@ -1924,19 +3980,44 @@ impl Default for MindMapApp {
             show_edge_color_picker: false,
             edge_color_picker_id: None,
             selected_edge_color: egui::Color32::WHITE,
+            show_edge_label_dialog: false,
+            edge_label_id: None,
+            edit_edge_label: String::new(),
             show_tags_panel: false,
             show_add_tag_dialog: false,
             show_edit_tag_dialog: false,
             edit_tag_id: None,
             edit_tag: EditableTag::default(),
             tags_node_id: None,
+            show_search_panel: false,
+            search_query: String::new(),
+            search_results: Vec::new(),
+            search_index: None,
+            indexer: None,
+            canvas_rect: egui::Rect::NOTHING,
+            notice: None,
+            layout: None,
+            citation_scans: Vec::new(),
+            link_proposals: Vec::new(),
+            show_duplicates: false,
+            duplicate_groups: Vec::new(),
+            show_analytics_panel: false,
+            analytics: None,
+            analytics_for: None,
+            size_metric: None,
+            node_scale: Default::default(),
+            highlighted_path: Vec::new(),
+            path_directed: false,
+            edge_routing: EdgeRouting::Straight,
+            show_minimap: false,
+            minimap_bounds: None,
+            spatial_index: SpatialIndex::new(EdgeRouting::Straight),
+            node_layouts: std::collections::HashMap::new(),
+            font_atlas_fill: 0.0,
         };
         // Load last file if it exists
         if let Ok(last_file) = load_last_file() {
-            app.current_file = Some(last_file);
-            if let Ok(loaded_map) = load_map(&app.current_file.as_ref().unwrap()) {
-                app.map = loaded_map;
-            }
+            app.open_project(&last_file);
         }
         app
     }
@@ -1949,14 +4030,24 @@ impl eframe::App for MindMapApp {
         });
 
         // autosave
-        if self.dirty && self.last_save.elapsed().as_secs() > 5 {
-            if let Some(path) = &self.current_file {
-                let _ = save_map(&self.map, path);
-                self.last_save = std::time::Instant::now();
-                self.dirty = false;
-            }
+        if self.dirty && self.last_save.elapsed().as_secs() > 5 && let Some(path) = &self.current_file {
+            let _ = save_map(&self.map, path);
+            self.last_save = std::time::Instant::now();
+            self.dirty = false;
         }
 
+        // pick up results from the background indexer
+        self.poll_indexer(ctx);
+
+        // animate a running automatic layout
+        self.run_layout(ctx);
+
+        // pick up bibliographies read in the background
+        self.poll_citation_scans(ctx);
+
+        // keep graph metrics and node scaling in step with the map
+        self.update_analytics();
+
         // menu bar
         self.menu_bar(ctx);
 
@@ -1974,20 +4065,41 @@ impl eframe::App for MindMapApp {
         self.show_tags_panel(ctx);
         self.show_tag_dialog(ctx);
 
+        // Show full-text search panel
+        self.show_search_panel(ctx);
+
         // Show edge context menu if active
         self.show_edge_context_menu(ctx);
 
+        // Show frame context menu and dialog if active
+        self.show_frame_context_menu(ctx);
+        self.show_frame_dialog(ctx);
+
         // Edge type selection menu
         self.show_edge_type_menu(ctx);
+        self.show_edge_types_dialog(ctx);
 
         // Show create project prompt if needed
         self.show_create_project_promt(ctx);
 
+        // Show proposed citation links
+        self.show_link_review(ctx);
+
+        // Show duplicate nodes
+        self.show_duplicates_window(ctx);
+
+        // Show graph analytics
+        self.show_analytics_panel(ctx);
+
+        // Show migration and error notices
+        self.show_notice(ctx);
+
         // Show node color picker if active
         self.show_node_color_picker(ctx);
 
         // Show edge color picker if active
         self.show_edge_color_picker(ctx);
+        self.show_edge_label_dialog(ctx);
 
         // main panel
         self.main_view(ctx);
@@ -2021,12 +4133,18 @@ fn get_node_rect(ctx: &egui::Context, node: &Node, zoom: f32) -> egui::Rect {
             // Calculate space for metadata fields
             let authors_str = metadata.authors.join(", ");
             let keywords_str = metadata.keywords.join(", ");
-            let fields = vec![
+            let mut fields = vec![
                 ("Title: ", &metadata.title),
                 ("Authors: ", &authors_str),
                 ("Keywords: ", &keywords_str),
                 ("Date: ", &metadata.date),
             ];
+            if let Some(doi) = &metadata.doi {
+                fields.push(("DOI: ", doi));
+            }
+            if let Some(arxiv) = &metadata.arxiv {
+                fields.push(("arXiv: ", arxiv));
+            }
 
             let label_width = MindMapApp::find_widest_label(ctx, fields.clone(), zoom);
 
@@ -2052,6 +4170,12 @@ fn get_node_rect(ctx: &egui::Context, node: &Node, zoom: f32) -> egui::Rect {
     egui::Rect::from_center_size(egui::pos2(node.x, node.y), node_size)
 }
 
+// An edited identifier, recognised by `find` or kept as typed when it isn't
+fn identifier(value: &str, find: impl Fn(&str) -> Option<String>) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| find(value).unwrap_or_else(|| value.to_string()))
+}
+
 fn point_line_distance(a: Pos2, b: Pos2, p: Pos2) -> f32 {
     let ap = p - a;
     let ab = b - a;
@@ -2064,8 +4188,133 @@ fn point_line_distance(a: Pos2, b: Pos2, p: Pos2) -> f32 {
     (p - proj).length()
 }
 
+fn color32(color: [u8; 4]) -> egui::Color32 {
+    egui::Color32::from_rgba_unmultiplied(color[0], color[1], color[2], color[3])
+}
+
+// Points from the border of `from` to the border of `to`, in screen
+// coordinates. `offset` pushes the middle of the edge sideways, away from
+// edges between the same nodes.
+fn route_edge(from: egui::Rect, to: egui::Rect, routing: EdgeRouting, offset: egui::Vec2) -> Vec<Pos2> {
+    let (start, end) = (from.center(), to.center());
+    match routing {
+        EdgeRouting::Straight if offset.length() < f32::EPSILON => {
+            vec![border_point(from, end), border_point(to, start)]
+        }
+        EdgeRouting::Straight | EdgeRouting::Curved => {
+            // A quadratic curve peaks halfway to its control point
+            let mut control = start + (end - start) * 0.5 + offset * 2.0;
+            if routing == EdgeRouting::Curved {
+                let d = end - start;
+                control += egui::vec2(-d.y, d.x) * EDGE_CURVE_BEND;
+            }
+            bezier(&[border_point(from, control), control, border_point(to, control)])
+        }
+        EdgeRouting::Orthogonal => {
+            let d = end - start;
+            if d.x.abs() >= d.y.abs() {
+                // Out of the side facing the target, across, and into the target's side
+                let sign = d.x.signum();
+                let shift = |rect: egui::Rect| offset.y.clamp(-rect.height() / 2.0, rect.height() / 2.0);
+                let first = egui::pos2(if sign > 0.0 { from.right() } else { from.left() }, start.y + shift(from));
+                let last = egui::pos2(if sign > 0.0 { to.left() } else { to.right() }, end.y + shift(to));
+                let middle = (first.x + last.x) / 2.0 + offset.x;
+                vec![first, egui::pos2(middle, first.y), egui::pos2(middle, last.y), last]
+            } else {
+                let sign = d.y.signum();
+                let shift = |rect: egui::Rect| offset.x.clamp(-rect.width() / 2.0, rect.width() / 2.0);
+                let first = egui::pos2(start.x + shift(from), if sign > 0.0 { from.bottom() } else { from.top() });
+                let last = egui::pos2(end.x + shift(to), if sign > 0.0 { to.top() } else { to.bottom() });
+                let middle = (first.y + last.y) / 2.0 + offset.y;
+                vec![first, egui::pos2(first.x, middle), egui::pos2(last.x, middle), last]
+            }
+        }
+    }
+}
+
+fn snap_to_grid(value: f32, size: f32) -> f32 {
+    (value / size).round() * size
+}
+
+// The smallest shift along one axis that lines up one of `lines` (a node's
+// leading edge, center and trailing edge) with a line of another node, if any
+// is within `tolerance`
+fn alignment(lines: [f32; 3], others: &[[f32; 3]], tolerance: f32) -> Option<f32> {
+    others.iter()
+        .flat_map(|other| other.iter().flat_map(move |b| lines.map(move |a| b - a)))
+        .filter(|shift| shift.abs() < tolerance)
+        .min_by(|a, b| a.abs().total_cmp(&b.abs()))
+}
+
+fn bounds(rect: egui::Rect) -> Bounds {
+    [rect.min.x, rect.min.y, rect.max.x, rect.max.y]
+}
+
+// Where the line from the centre of `rect` towards `target` leaves the rect
+fn border_point(rect: egui::Rect, target: Pos2) -> Pos2 {
+    let center = rect.center();
+    let d = target - center;
+    let half = rect.size() / 2.0;
+    let scale = [half.x / d.x.abs(), half.y / d.y.abs()].into_iter()
+        .filter(|s| s.is_finite())
+        .fold(1.0_f32, f32::min);
+    center + d * scale
+}
+
+// Loop from the top of a node back into its right side; `size` grows for
+// each further loop on the same node
+fn self_loop(rect: egui::Rect, size: f32) -> Vec<Pos2> {
+    let start = rect.right_top() + egui::vec2(-16.0, 0.0);
+    let end = rect.right_top() + egui::vec2(0.0, 16.0);
+    let reach = 24.0 + 12.0 * size;
+    bezier(&[start, start + egui::vec2(0.0, -reach), end + egui::vec2(reach, 0.0), end])
+}
+
+// Points along the Bezier curve through `controls`
+fn bezier(controls: &[Pos2]) -> Vec<Pos2> {
+    (0..=CURVE_SEGMENTS)
+        .map(|i| {
+            let t = i as f32 / CURVE_SEGMENTS as f32;
+            let mut points = controls.to_vec();
+            while points.len() > 1 {
+                points = points.windows(2).map(|w| w[0].lerp(w[1], t)).collect();
+            }
+            points[0]
+        })
+        .collect()
+}
+
+// The point halfway along a polyline
+fn polyline_midpoint(points: &[Pos2]) -> Pos2 {
+    let half = points.windows(2).map(|w| w[0].distance(w[1])).sum::<f32>() / 2.0;
+    let mut travelled = 0.0;
+    for w in points.windows(2) {
+        let length = w[0].distance(w[1]);
+        if travelled + length >= half && length > 0.0 {
+            return w[0].lerp(w[1], (half - travelled) / length);
+        }
+        travelled += length;
+    }
+    points.first().copied().unwrap_or_default()
+}
+
+// Stroke a polyline solid, dashed or dotted
+fn draw_line(painter: &egui::Painter, points: &[Pos2], stroke: egui::Stroke, dash: DashStyle) {
+    match dash {
+        DashStyle::Solid => {
+            painter.add(egui::Shape::line(points.to_vec(), stroke));
+        }
+        DashStyle::Dashed => {
+            painter.extend(egui::Shape::dashed_line(points, stroke, 4.0 * stroke.width + 4.0, 2.0 * stroke.width + 2.0));
+        }
+        DashStyle::Dotted => {
+            painter.extend(egui::Shape::dotted_line(points, stroke.color, 2.0 * stroke.width + 2.0, stroke.width * 0.6));
+        }
+    }
+}
+
 fn get_popup_frame() -> egui::Frame {
-    let frame = egui::Frame{
+    egui::Frame{
         inner_margin: Default::default(),
         fill: egui::Color32::from_hex("#2b2c36").unwrap(),
         stroke: Default::default(),
@@ -2077,7 +4326,6 @@ fn get_popup_frame() -> egui::Frame {
             spread: 0,
             color: egui::Color32::BLACK,
         },
-    };
-    frame
+    }
 }
 
@@ -0,0 +1,315 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+use crate::core::map::{MindMap, Node, Tag};
+use crate::core::references::Format;
+use crate::core::storage::{export_project, import_project, load_last_file, load_map, load_map_file, save_last_file, save_map};
+
+// Colour given to tags created from the command line
+const DEFAULT_TAG_COLOR: [u8; 4] = [100, 149, 237, 255];
+// Vertical distance between PDFs added from the command line
+const ADD_SPACING: f32 = 90.0;
+
+const USAGE: &str = "\
+Usage: refmap [--project <dir>] <command> [args]
+
+Without a command the graphical editor is started.
+
+Commands:
+  open <dir>                    Make <dir> the current project
+  import <zip> <dir>            Unpack an exported project into <dir> and make it current
+  list nodes|edges|tags|frames  Print the map, one item per line
+  add-pdf <file>                Copy a PDF into the project and add a node for it
+  tag <node> <tag>              Tag a node (by id, id prefix or title); the tag is created if needed
+  export zip <file>             Write the project as a zip archive
+  export bib|ris|json [<file>]  Write BibTeX, RIS or CSL-JSON, to stdout without <file>
+  validate                      Check the map for dangling references and missing PDFs
+
+Commands other than `open` and `import` work on --project, or on the project last
+opened in the editor or with `refmap open`.";
+
+/// Run a command-line invocation. `args` excludes the program name; the
+/// returned value is the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    match execute(args) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("refmap: {}", e);
+            1
+        }
+    }
+}
+
+fn execute(args: &[String]) -> Result<i32> {
+    let mut project: Option<String> = None;
+    let mut args = args;
+    while let Some(arg) = args.first() {
+        match arg.as_str() {
+            "-p" | "--project" => {
+                project = Some(args.get(1).ok_or_else(|| anyhow!("{} needs a directory", arg))?.clone());
+                args = &args[2..];
+            }
+            _ => break,
+        }
+    }
+
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => {
+            println!("{}", USAGE);
+            return Ok(0);
+        }
+    };
+
+    match (command, rest) {
+        ("help" | "-h" | "--help", _) => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        ("open", [dir]) => open(dir),
+        ("import", [zip, dir]) => {
+            import_project(zip, dir)?;
+            open(dir)
+        }
+        ("list", [what]) => {
+            let project_dir = project_dir(project)?;
+            list(&load_map(&project_dir)?, what)?;
+            Ok(0)
+        }
+        ("add-pdf", [file]) => {
+            let project_dir = project_dir(project)?;
+            add_pdf(&project_dir, Path::new(file))?;
+            Ok(0)
+        }
+        ("tag", [node, tag_name]) => {
+            let project_dir = project_dir(project)?;
+            tag(&project_dir, node, tag_name)?;
+            Ok(0)
+        }
+        ("export", [format, rest @ ..]) if rest.len() <= 1 => {
+            let project_dir = project_dir(project)?;
+            export(&project_dir, format, rest.first().map(Path::new))?;
+            Ok(0)
+        }
+        ("validate", []) => {
+            let project_dir = project_dir(project)?;
+            validate(&project_dir)
+        }
+        _ => Err(anyhow!("invalid arguments, run `refmap help` for usage")),
+    }
+}
+
+fn project_dir(project: Option<String>) -> Result<String> {
+    project
+        .or_else(|| load_last_file().ok())
+        .ok_or_else(|| anyhow!("no project given, use --project <dir> or `refmap open <dir>`"))
+}
+
+fn open(dir: &str) -> Result<i32> {
+    let dir = fs::canonicalize(dir)?.to_string_lossy().to_string();
+    let loaded = load_map_file(&dir).map_err(|e| anyhow!("{} is not a RefMap project: {}", dir, e))?;
+    if let Some(version) = loaded.migrated_from {
+        print_upgraded(version);
+        save_map(&loaded.map, &dir)?;
+    }
+    let map = loaded.map;
+    save_last_file(&dir)?;
+    println!("Opened {} ({} nodes, {} edges, {} tags)", dir, map.nodes.len(), map.edges.len(), map.tags.len());
+    Ok(0)
+}
+
+fn print_upgraded(version: u64) {
+    println!("Upgraded map.json from format version {}, the original is kept as map.v{}.json", version, version);
+}
+
+// Load a map that is about to be changed and saved, telling the user when the
+// save will upgrade an older map.json
+fn load_for_update(project_dir: &str) -> Result<MindMap> {
+    let loaded = load_map_file(project_dir)?;
+    if let Some(version) = loaded.migrated_from {
+        print_upgraded(version);
+    }
+    Ok(loaded.map)
+}
+
+fn list(map: &MindMap, what: &str) -> Result<()> {
+    match what {
+        "nodes" => {
+            for node in &map.nodes {
+                let tags: Vec<&str> = node.tags.iter()
+                    .filter_map(|id| map.tags.iter().find(|t| t.id == *id))
+                    .map(|t| t.name.as_str())
+                    .collect();
+                println!("{}\t{}\t{}\t{}", node.id, node.title, node.path.as_deref().unwrap_or(""), tags.join(","));
+            }
+        }
+        "edges" => {
+            for edge in &map.edges {
+                let edge_type = map.edge_type(edge.edge_type).map_or("", |t| t.name.as_str());
+                println!("{}\t{}\t{}\t{}", edge.id, edge.from, edge.to, edge_type);
+            }
+        }
+        "tags" => {
+            for tag in &map.tags {
+                let count = map.nodes.iter().filter(|n| n.tags.contains(&tag.id)).count();
+                println!("{}\t{}\t{}", tag.id, tag.name, count);
+            }
+        }
+        "frames" => {
+            for frame in &map.frames {
+                println!("{}\t{}\t{}", frame.id, frame.label, frame.nodes.len());
+            }
+        }
+        _ => return Err(anyhow!("cannot list '{}', expected nodes, edges, tags or frames", what)),
+    }
+    Ok(())
+}
+
+fn add_pdf(project_dir: &str, file: &Path) -> Result<()> {
+    let mut map = load_for_update(project_dir)?;
+
+    let pdfs_dir = Path::new(project_dir).join("pdfs");
+    if !pdfs_dir.exists() {
+        fs::create_dir_all(&pdfs_dir)?;
+    }
+    let file_name = file.file_name().ok_or_else(|| anyhow!("invalid PDF path {}", file.display()))?;
+    let dest_path = pdfs_dir.join(file_name);
+    if !dest_path.exists() {
+        fs::copy(file, &dest_path)?;
+    }
+
+    // Stack new nodes under the existing ones so they don't overlap
+    let x = map.nodes.iter().map(|n| n.x).reduce(f32::min).unwrap_or(0.0);
+    let y = map.nodes.iter().map(|n| n.y).reduce(f32::max).map_or(0.0, |y| y + ADD_SPACING);
+
+    let id = map.add_pdf_node(&dest_path.to_string_lossy(), x, y)?;
+    save_map(&map, project_dir)?;
+    println!("{}", id);
+    Ok(())
+}
+
+fn tag(project_dir: &str, node: &str, tag_name: &str) -> Result<()> {
+    let mut map = load_for_update(project_dir)?;
+    let node_id = find_node(&map, node)?.id;
+
+    let tag_id = match map.tags.iter().find(|t| t.name == tag_name) {
+        Some(tag) => tag.id,
+        None => {
+            let tag = Tag { name: tag_name.to_string(), color: DEFAULT_TAG_COLOR, id: Uuid::new_v4() };
+            let id = tag.id;
+            map.tags.push(tag);
+            id
+        }
+    };
+
+    if let Some(node) = map.nodes.iter_mut().find(|n| n.id == node_id) && !node.tags.contains(&tag_id) {
+        node.tags.push(tag_id);
+    }
+    save_map(&map, project_dir)
+}
+
+// Look a node up by full id, unique id prefix or exact title
+fn find_node<'a>(map: &'a MindMap, query: &str) -> Result<&'a Node> {
+    if let Ok(id) = Uuid::parse_str(query) {
+        return map.nodes.iter().find(|n| n.id == id).ok_or_else(|| anyhow!("no node with id {}", id));
+    }
+
+    let mut matches: Vec<&Node> = map.nodes.iter().filter(|n| n.title == query).collect();
+    if matches.is_empty() {
+        matches = map.nodes.iter().filter(|n| n.id.to_string().starts_with(query)).collect();
+    }
+    match matches.as_slice() {
+        [node] => Ok(node),
+        [] => Err(anyhow!("no node matches '{}'", query)),
+        _ => Err(anyhow!("'{}' matches {} nodes, use the node id", query, matches.len())),
+    }
+}
+
+fn export(project_dir: &str, format: &str, output: Option<&Path>) -> Result<()> {
+    let format = match format {
+        "zip" => {
+            let output = output.ok_or_else(|| anyhow!("export zip needs an output file"))?;
+            return export_project(project_dir, &output.to_string_lossy());
+        }
+        "bib" => Format::BibTeX,
+        "ris" => Format::Ris,
+        "json" => Format::CslJson,
+        _ => return Err(anyhow!("unknown export format '{}', expected zip, bib, ris or json", format)),
+    };
+
+    let map = load_map(project_dir)?;
+    match output {
+        Some(output) => format.export_file(&map, None, output),
+        None => {
+            print!("{}", format.export(&map, None)?);
+            Ok(())
+        }
+    }
+}
+
+fn validate(project_dir: &str) -> Result<i32> {
+    let map = load_map(project_dir)?;
+    let mut problems = Vec::new();
+
+    let mut node_ids = HashSet::new();
+    for node in &map.nodes {
+        if !node_ids.insert(node.id) {
+            problems.push(format!("duplicate node id {}", node.id));
+        }
+    }
+
+    let mut edge_ids = HashSet::new();
+    for edge in &map.edges {
+        if !edge_ids.insert(edge.id) {
+            problems.push(format!("duplicate edge id {}", edge.id));
+        }
+        for end in [edge.from, edge.to] {
+            if !node_ids.contains(&end) {
+                problems.push(format!("edge {} points at missing node {}", edge.id, end));
+            }
+        }
+        if map.edge_type(edge.edge_type).is_none() {
+            problems.push(format!("edge {} has missing edge type {}", edge.id, edge.edge_type));
+        }
+    }
+
+    let tag_ids: HashSet<Uuid> = map.tags.iter().map(|t| t.id).collect();
+    for node in &map.nodes {
+        for tag in &node.tags {
+            if !tag_ids.contains(tag) {
+                problems.push(format!("node {} ({}) has missing tag {}", node.id, node.title, tag));
+            }
+        }
+        if let Some(path) = &node.path {
+            let path = PathBuf::from(path);
+            let path = if path.is_relative() { Path::new(project_dir).join(path) } else { path };
+            if !path.exists() {
+                problems.push(format!("node {} ({}) links to missing PDF {}", node.id, node.title, path.display()));
+            }
+        }
+    }
+
+    for frame in &map.frames {
+        if frame.nodes.is_empty() {
+            problems.push(format!("frame {} ({}) contains no nodes", frame.id, frame.label));
+        }
+        for member in &frame.nodes {
+            if !node_ids.contains(member) {
+                problems.push(format!("frame {} ({}) contains missing node {}", frame.id, frame.label, member));
+            }
+        }
+    }
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("{}: {} nodes, {} edges, {} tags, no problems found", project_dir, map.nodes.len(), map.edges.len(), map.tags.len());
+        Ok(0)
+    } else {
+        println!("{} problem(s) found", problems.len());
+        Ok(1)
+    }
+}
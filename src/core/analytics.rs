@@ -0,0 +1,203 @@
+use std::collections::{HashMap, VecDeque};
+use uuid::Uuid;
+use crate::core::map::{EdgeType, MindMap};
+
+// Probability of following a citation rather than jumping to a random paper
+const DAMPING: f32 = 0.85;
+const PAGERANK_ITERATIONS: usize = 100;
+// PageRank has converged once no score changes by more than this
+const PAGERANK_TOLERANCE: f32 = 1e-6;
+
+/// Metrics computed over the whole map. Degrees and PageRank only follow
+/// References edges (citing paper -> cited paper); components and orphans
+/// consider every edge.
+#[derive(Debug, Clone, Default)]
+pub struct Analytics {
+    pub in_degree: HashMap<Uuid, usize>,  // times a paper is cited on the map
+    pub out_degree: HashMap<Uuid, usize>, // papers on the map it cites
+    pub pagerank: HashMap<Uuid, f32>,     // sums to 1 over all nodes
+    pub components: Vec<Vec<Uuid>>,       // connected groups of nodes, largest first
+    pub orphans: Vec<Uuid>,               // nodes without any edge
+}
+
+/// A per-node metric that nodes can be ranked and sized by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    InDegree,
+    OutDegree,
+    PageRank,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 3] = [Metric::InDegree, Metric::OutDegree, Metric::PageRank];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Metric::InDegree => "Cited by (in-degree)",
+            Metric::OutDegree => "Cites (out-degree)",
+            Metric::PageRank => "PageRank",
+        }
+    }
+}
+
+impl Analytics {
+    pub fn compute(map: &MindMap) -> Analytics {
+        let index: HashMap<Uuid, usize> = map.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+        let edges: Vec<(usize, usize, bool)> = map.edges.iter()
+            .filter_map(|e| Some((*index.get(&e.from)?, *index.get(&e.to)?, e.edge_type == EdgeType::REFERENCES)))
+            .collect();
+        let citations: Vec<(usize, usize)> = edges.iter()
+            .filter(|(from, to, references)| *references && from != to)
+            .map(|(from, to, _)| (*from, *to))
+            .collect();
+
+        let mut in_degree = vec![0; map.nodes.len()];
+        let mut out_degree = vec![0; map.nodes.len()];
+        for (from, to) in &citations {
+            out_degree[*from] += 1;
+            in_degree[*to] += 1;
+        }
+
+        let pagerank = pagerank(map.nodes.len(), &citations, &out_degree);
+        let components = components(map.nodes.len(), &edges);
+
+        let ids = |values: Vec<usize>| -> Vec<Uuid> { values.into_iter().map(|i| map.nodes[i].id).collect() };
+        let by_id = |values: Vec<usize>| -> HashMap<Uuid, usize> {
+            map.nodes.iter().zip(values).map(|(n, v)| (n.id, v)).collect()
+        };
+
+        let mut linked = vec![false; map.nodes.len()];
+        for (from, to, _) in &edges {
+            linked[*from] = true;
+            linked[*to] = true;
+        }
+        let orphans = (0..map.nodes.len()).filter(|i| !linked[*i]).collect();
+
+        Analytics {
+            in_degree: by_id(in_degree),
+            out_degree: by_id(out_degree),
+            pagerank: map.nodes.iter().zip(pagerank).map(|(n, r)| (n.id, r)).collect(),
+            components: components.into_iter().map(ids).collect(),
+            orphans: ids(orphans),
+        }
+    }
+
+    pub fn value(&self, metric: Metric, node_id: Uuid) -> f32 {
+        match metric {
+            Metric::InDegree => self.in_degree.get(&node_id).copied().unwrap_or(0) as f32,
+            Metric::OutDegree => self.out_degree.get(&node_id).copied().unwrap_or(0) as f32,
+            Metric::PageRank => self.pagerank.get(&node_id).copied().unwrap_or(0.0),
+        }
+    }
+
+    /// Nodes with a non-zero value for `metric`, highest first.
+    pub fn ranking(&self, metric: Metric) -> Vec<(Uuid, f32)> {
+        // Without citations PageRank is the same for every node and ranks nothing
+        if metric == Metric::PageRank && self.in_degree.values().all(|d| *d == 0) {
+            return Vec::new();
+        }
+        // Every metric has a value for every node
+        let mut ranking: Vec<(Uuid, f32)> = self.pagerank.keys()
+            .map(|id| (*id, self.value(metric, *id)))
+            .filter(|(_, value)| *value > 0.0)
+            .collect();
+        ranking.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranking
+    }
+}
+
+// Power iteration. Papers that cite nothing on the map spread their rank over
+// all papers, so the scores keep summing to 1.
+fn pagerank(count: usize, citations: &[(usize, usize)], out_degree: &[usize]) -> Vec<f32> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let uniform = 1.0 / count as f32;
+    let mut rank = vec![uniform; count];
+
+    for _ in 0..PAGERANK_ITERATIONS {
+        let dangling: f32 = rank.iter().zip(out_degree).filter(|(_, d)| **d == 0).map(|(r, _)| r).sum();
+        let base = (1.0 - DAMPING) * uniform + DAMPING * dangling * uniform;
+        let mut next = vec![base; count];
+        for (from, to) in citations {
+            next[*to] += DAMPING * rank[*from] / out_degree[*from] as f32;
+        }
+
+        let change = rank.iter().zip(&next).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+        rank = next;
+        if change < PAGERANK_TOLERANCE {
+            break;
+        }
+    }
+    rank
+}
+
+// Connected components ignoring edge direction, largest first
+fn components(count: usize, edges: &[(usize, usize, bool)]) -> Vec<Vec<usize>> {
+    let mut neighbours = vec![Vec::new(); count];
+    for (from, to, _) in edges {
+        neighbours[*from].push(*to);
+        neighbours[*to].push(*from);
+    }
+
+    let mut seen = vec![false; count];
+    let mut components = Vec::new();
+    for start in 0..count {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut component = vec![start];
+        let mut next = 0;
+        while next < component.len() {
+            for &neighbour in &neighbours[component[next]] {
+                if !seen[neighbour] {
+                    seen[neighbour] = true;
+                    component.push(neighbour);
+                }
+            }
+            next += 1;
+        }
+        components.push(component);
+    }
+
+    components.sort_by_key(|c| std::cmp::Reverse(c.len()));
+    components
+}
+
+/// The fewest edges leading from `from` to `to`, in order, found by a
+/// breadth-first search over edges of every type. With `directed` set edges
+/// of directed types are only followed from their `from` to their `to` end.
+/// None when the nodes are not connected.
+pub fn shortest_path(map: &MindMap, from: Uuid, to: Uuid, directed: bool) -> Option<Vec<Uuid>> {
+    let mut neighbours: HashMap<Uuid, Vec<(Uuid, Uuid)>> = HashMap::new(); // node -> (edge, next node)
+    for edge in &map.edges {
+        neighbours.entry(edge.from).or_default().push((edge.id, edge.to));
+        let one_way = directed && map.edge_type(edge.edge_type).is_some_and(|t| t.directed);
+        if !one_way {
+            neighbours.entry(edge.to).or_default().push((edge.id, edge.from));
+        }
+    }
+
+    let mut reached_by: HashMap<Uuid, Option<(Uuid, Uuid)>> = HashMap::from([(from, None)]); // node -> (edge, previous node)
+    let mut queue = VecDeque::from([from]);
+    while let Some(node) = queue.pop_front() {
+        if node == to {
+            let mut edges = Vec::new();
+            let mut current = to;
+            while let Some(Some((edge, previous))) = reached_by.get(&current) {
+                edges.push(*edge);
+                current = *previous;
+            }
+            edges.reverse();
+            return Some(edges);
+        }
+        for (edge, next) in neighbours.get(&node).into_iter().flatten() {
+            if !reached_by.contains_key(next) {
+                reached_by.insert(*next, Some((*edge, node)));
+                queue.push_back(*next);
+            }
+        }
+    }
+    None
+}
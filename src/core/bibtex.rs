@@ -0,0 +1,613 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+use crate::core::identifiers;
+use crate::core::map::MindMap;
+use crate::core::pdfparser::{Metadata, PersonName};
+use crate::core::references::{self, Reference};
+
+/// A single `@type{key, field = value, ...}` entry.
+#[derive(Debug, Clone, Default)]
+pub struct BibEntry {
+    pub entry_type: String,
+    pub key: String,
+    pub fields: Vec<(String, String)>, // lowercase field name, value with LaTeX markup removed
+}
+
+impl BibEntry {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.trim().is_empty())
+    }
+
+    pub fn to_metadata(&self) -> Metadata {
+        let names = self.field("author")
+            .or_else(|| self.field("editor"))
+            .map(parse_names)
+            .unwrap_or_default();
+
+        let keywords = self.field("keywords")
+            .map(|k| k.split([',', ';']).map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+
+        // biblatex uses `date`, classic BibTeX `year` (and optionally `month`)
+        let date = self.field("date").map(str::to_string).unwrap_or_else(|| {
+            let year = self.field("year").unwrap_or_default().to_string();
+            match self.field("month").and_then(month_number) {
+                Some(month) if !year.is_empty() => format!("{}-{:02}", year, month),
+                _ => year,
+            }
+        });
+
+        let venue = self.field("journal")
+            .or_else(|| self.field("journaltitle"))
+            .or_else(|| self.field("booktitle"))
+            .or_else(|| self.field("publisher"))
+            .or_else(|| self.field("school"))
+            .map(str::to_string);
+
+        let mut metadata = Metadata {
+            title: self.field("title").unwrap_or(&self.key).to_string(),
+            keywords,
+            date,
+            doi: self.field("doi").map(str::to_string),
+            arxiv: self.arxiv(),
+            venue,
+            citation_key: Some(self.key.clone()),
+            entry_type: Some(self.entry_type.clone()),
+            ..Default::default()
+        };
+        metadata.set_authors(names);
+        metadata
+    }
+
+    // `eprint` with `archiveprefix` (BibTeX) or `eprinttype` (biblatex) set to
+    // arXiv, or an arXiv URL
+    fn arxiv(&self) -> Option<String> {
+        let archive = self.field("archiveprefix").or_else(|| self.field("eprinttype")).unwrap_or_default();
+        let eprint = self.field("eprint").filter(|_| archive.eq_ignore_ascii_case("arxiv"));
+        eprint.and_then(|id| identifiers::find_arxiv(&format!("arXiv:{}", id)))
+            .or_else(|| self.field("url").and_then(identifiers::find_arxiv))
+    }
+
+    /// Resolve the first PDF listed in the `file` field. Supports plain paths as
+    /// well as the `description:path:type` form written by JabRef and Zotero.
+    pub fn pdf_file(&self, bib_dir: &Path) -> Option<PathBuf> {
+        let field = self.field("file")?;
+        for entry in field.split(';') {
+            let candidate = entry.split(':')
+                .find(|part| part.to_lowercase().ends_with(".pdf"))
+                .map(str::to_string)
+                // Windows paths contain a drive colon, so glue "C" and "\dir\x.pdf" back together
+                .or_else(|| {
+                    let parts: Vec<&str> = entry.split(':').collect();
+                    parts.windows(2)
+                        .find(|w| w[0].len() == 1 && w[1].to_lowercase().ends_with(".pdf"))
+                        .map(|w| format!("{}:{}", w[0], w[1]))
+                });
+
+            if let Some(candidate) = candidate {
+                let path = PathBuf::from(candidate.replace("\\:", ":"));
+                let path = if path.is_relative() { bib_dir.join(path) } else { path };
+                if path.exists() {
+                    return Some(path);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Parse every entry in a BibTeX file. `@string` macros are expanded,
+/// `@comment` and `@preamble` blocks are skipped.
+pub fn parse(input: &str) -> Result<Vec<BibEntry>> {
+    let mut parser = Parser { chars: input.chars().collect(), pos: 0, strings: default_strings() };
+    let mut entries = Vec::new();
+
+    while parser.skip_to('@') {
+        parser.pos += 1;
+        let entry_type = parser.identifier().to_lowercase();
+        parser.skip_whitespace();
+
+        let close = match parser.peek() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => continue, // stray '@' in free text between entries
+        };
+        parser.pos += 1;
+
+        match entry_type.as_str() {
+            "comment" | "preamble" => parser.skip_group(close),
+            "string" => {
+                parser.skip_whitespace();
+                let name = parser.identifier().to_lowercase();
+                parser.skip_whitespace();
+                if parser.peek() == Some('=') {
+                    parser.pos += 1;
+                    let value = parser.value()?;
+                    parser.strings.insert(name, value);
+                }
+                parser.skip_group(close);
+            }
+            _ => {
+                parser.skip_whitespace();
+                let key = parser.until(&[',', close]).trim().to_string();
+                let mut entry = BibEntry { entry_type, key, fields: Vec::new() };
+
+                loop {
+                    parser.skip_whitespace();
+                    match parser.peek() {
+                        Some(',') => parser.pos += 1,
+                        Some(c) if c == close => {
+                            parser.pos += 1;
+                            break;
+                        }
+                        None => return Err(anyhow!("Unterminated entry '{}'", entry.key)),
+                        _ => {
+                            let name = parser.identifier().to_lowercase();
+                            parser.skip_whitespace();
+                            if name.is_empty() || parser.peek() != Some('=') {
+                                return Err(anyhow!("Malformed field in entry '{}'", entry.key));
+                            }
+                            parser.pos += 1;
+                            let value = parser.value()?;
+                            // Names are cleaned after splitting, paths must keep their backslashes
+                            let value = match name.as_str() {
+                                "author" | "editor" | "file" | "url" | "doi" | "eprint" => value.trim().to_string(),
+                                _ => clean_value(&value),
+                            };
+                            entry.fields.push((name, value));
+                        }
+                    }
+                }
+
+                entries.push(entry);
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+pub fn parse_file(path: &Path) -> Result<Vec<BibEntry>> {
+    parse(&fs::read_to_string(path)?)
+}
+
+/// Read a BibTeX file as references, resolving PDFs relative to the file.
+pub fn import_file(path: &Path) -> Result<Vec<Reference>> {
+    let bib_dir = path.parent().unwrap_or(Path::new("."));
+    let references = parse_file(path)?
+        .iter()
+        .map(|entry| Reference { metadata: entry.to_metadata(), pdf: entry.pdf_file(bib_dir) })
+        .collect();
+    Ok(references)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    strings: HashMap<String, String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_to(&mut self, target: char) -> bool {
+        while let Some(c) = self.peek() {
+            if c == target {
+                return true;
+            }
+            self.pos += 1;
+        }
+        false
+    }
+
+    fn identifier(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || "_-:.+/".contains(c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    fn until(&mut self, stops: &[char]) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !stops.contains(&c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    // Skip the rest of a block, honouring nested braces
+    fn skip_group(&mut self, close: char) {
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' if depth > 0 => depth -= 1,
+                c if c == close && depth == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    // A field value: braced or quoted strings, numbers and macros joined with '#'
+    fn value(&mut self) -> Result<String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('{') => {
+                    self.pos += 1;
+                    value.push_str(&self.braced()?);
+                }
+                Some('"') => {
+                    self.pos += 1;
+                    value.push_str(&self.quoted()?);
+                }
+                Some(_) => {
+                    let word = self.identifier();
+                    if word.is_empty() {
+                        return Err(anyhow!("Unexpected character in value"));
+                    }
+                    match self.strings.get(&word.to_lowercase()) {
+                        Some(expanded) => value.push_str(expanded),
+                        None => value.push_str(&word),
+                    }
+                }
+                None => return Err(anyhow!("Unexpected end of input")),
+            }
+
+            self.skip_whitespace();
+            if self.peek() == Some('#') {
+                self.pos += 1;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    // Contents of a {...} group, keeping inner braces for later cleanup
+    fn braced(&mut self) -> Result<String> {
+        let mut depth = 0;
+        let mut value = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(value),
+                '}' => depth -= 1,
+                _ => {}
+            }
+            value.push(c);
+        }
+        Err(anyhow!("Unbalanced braces"))
+    }
+
+    fn quoted(&mut self) -> Result<String> {
+        let mut depth = 0;
+        let mut value = String::new();
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '"' if depth == 0 => return Ok(value),
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            value.push(c);
+        }
+        Err(anyhow!("Unterminated string"))
+    }
+}
+
+fn default_strings() -> HashMap<String, String> {
+    ["january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november", "december"]
+        .iter()
+        .map(|month| (month[..3].to_string(), month[..1].to_uppercase() + &month[1..]))
+        .collect()
+}
+
+fn month_number(month: &str) -> Option<u32> {
+    if let Ok(number) = month.trim().parse::<u32>() {
+        return (1..=12).contains(&number).then_some(number);
+    }
+    let month = month.trim().to_lowercase();
+    ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"]
+        .iter()
+        .position(|m| month.starts_with(m))
+        .map(|i| i as u32 + 1)
+}
+
+/// Split a BibTeX name list on top-level " and " into given and family names.
+/// "Last, First" and "Last, Jr, First" are split on the commas, a name wrapped
+/// in braces such as "{World Health Organization}" is kept whole.
+pub fn parse_names(value: &str) -> Vec<PersonName> {
+    let mut names = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    let words: Vec<&str> = value.split_whitespace().collect();
+
+    for word in words {
+        if depth == 0 && word == "and" {
+            names.push(std::mem::take(&mut current));
+            continue;
+        }
+        depth += word.matches('{').count() as i32 - word.matches('}').count() as i32;
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    names.push(current);
+
+    names.into_iter()
+        .map(|name| {
+            let parts = split_top_level(&name, ',');
+            match parts.as_slice() {
+                [last, first] => PersonName { given: clean_value(first), family: clean_value(last) },
+                [last, jr, first] => PersonName {
+                    given: clean_value(first),
+                    family: format!("{}, {}", clean_value(last), clean_value(jr)),
+                },
+                _ if split_top_level(&name, ' ').len() == 1 => PersonName { given: String::new(), family: clean_value(&name) },
+                _ => PersonName::parse(&clean_value(&name)),
+            }
+        })
+        .filter(|name| !name.family.is_empty())
+        .collect()
+}
+
+// Split on `separator` outside of braces
+fn split_top_level(value: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut depth = 0;
+    for c in value.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(String::new());
+                continue;
+            }
+            _ => {}
+        }
+        parts.last_mut().unwrap().push(c);
+    }
+    parts.iter().map(|p| p.trim().to_string()).collect()
+}
+
+/// Strip protective braces and translate the most common LaTeX escapes and
+/// accent commands to Unicode.
+pub fn clean_value(value: &str) -> String {
+    let mut out = String::new();
+    let chars: Vec<char> = value.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '{' | '}' => i += 1,
+            '~' => {
+                out.push(' ');
+                i += 1;
+            }
+            '\\' if i + 1 < chars.len() => {
+                let command = chars[i + 1];
+                if "&%$#_{}".contains(command) {
+                    out.push(command);
+                    i += 2;
+                } else if let Some(accent) = accent_combining(command).filter(|_| {
+                    // \c{c} is an accent, \cite{...} is a command of its own
+                    !command.is_ascii_alphabetic() || !chars.get(i + 2).is_some_and(|c| c.is_ascii_alphabetic())
+                }) {
+                    // \"o, \"{o}, {\"o}
+                    let mut j = i + 2;
+                    while j < chars.len() && (chars[j] == '{' || chars[j] == ' ') {
+                        j += 1;
+                    }
+                    if j < chars.len() {
+                        let base = if chars[j] == '\\' && j + 1 < chars.len() && chars[j + 1] == 'i' {
+                            j += 1;
+                            'i'
+                        } else {
+                            chars[j]
+                        };
+                        out.push(base);
+                        out.push(accent);
+                        j += 1;
+                    }
+                    i = j;
+                } else {
+                    // Named commands: \ss, \o, \textit{...} etc.
+                    let mut j = i + 1;
+                    while j < chars.len() && chars[j].is_ascii_alphabetic() {
+                        j += 1;
+                    }
+                    let name: String = chars[i + 1..j].iter().collect();
+                    match name.as_str() {
+                        "ss" => out.push('ß'),
+                        "o" => out.push('ø'),
+                        "O" => out.push('Ø'),
+                        "aa" => out.push('å'),
+                        "AA" => out.push('Å'),
+                        "ae" => out.push('æ'),
+                        "AE" => out.push('Æ'),
+                        "l" => out.push('ł'),
+                        "L" => out.push('Ł'),
+                        "i" => out.push('ı'),
+                        "textasciitilde" => out.push('~'),
+                        "textasciicircum" => out.push('^'),
+                        "textbackslash" => out.push('\\'),
+                        _ => {} // formatting commands: keep their argument, drop the command
+                    }
+                    if j < chars.len() && chars[j] == ' ' && !name.is_empty() {
+                        j += 1;
+                    }
+                    i = j.max(i + 1);
+                }
+            }
+            c if c.is_whitespace() => {
+                if !out.ends_with(' ') {
+                    out.push(' ');
+                }
+                i += 1;
+            }
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                let dashes = chars[i..].iter().take_while(|c| **c == '-').count();
+                out.push(if dashes >= 3 { '—' } else { '–' });
+                i += dashes;
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    // Recombine base letters with their accents where possible (e.g. "o\u{308}" -> "ö")
+    compose(out.trim())
+}
+
+fn accent_combining(command: char) -> Option<char> {
+    match command {
+        '\'' => Some('\u{301}'),
+        '`' => Some('\u{300}'),
+        '^' => Some('\u{302}'),
+        '"' => Some('\u{308}'),
+        '~' => Some('\u{303}'),
+        '=' => Some('\u{304}'),
+        '.' => Some('\u{307}'),
+        'c' => Some('\u{327}'),
+        'v' => Some('\u{30C}'),
+        'u' => Some('\u{306}'),
+        'H' => Some('\u{30B}'),
+        'k' => Some('\u{328}'),
+        'r' => Some('\u{30A}'),
+        _ => None,
+    }
+}
+
+// Minimal canonical composition for the Latin letters produced above
+fn compose(value: &str) -> String {
+    const TABLE: &[(char, char, char)] = &[
+        ('a', '\u{301}', 'á'), ('e', '\u{301}', 'é'), ('i', '\u{301}', 'í'), ('o', '\u{301}', 'ó'), ('u', '\u{301}', 'ú'), ('y', '\u{301}', 'ý'),
+        ('A', '\u{301}', 'Á'), ('E', '\u{301}', 'É'), ('I', '\u{301}', 'Í'), ('O', '\u{301}', 'Ó'), ('U', '\u{301}', 'Ú'),
+        ('c', '\u{301}', 'ć'), ('n', '\u{301}', 'ń'), ('s', '\u{301}', 'ś'), ('z', '\u{301}', 'ź'),
+        ('a', '\u{300}', 'à'), ('e', '\u{300}', 'è'), ('i', '\u{300}', 'ì'), ('o', '\u{300}', 'ò'), ('u', '\u{300}', 'ù'),
+        ('A', '\u{300}', 'À'), ('E', '\u{300}', 'È'),
+        ('a', '\u{302}', 'â'), ('e', '\u{302}', 'ê'), ('i', '\u{302}', 'î'), ('o', '\u{302}', 'ô'), ('u', '\u{302}', 'û'),
+        ('a', '\u{308}', 'ä'), ('e', '\u{308}', 'ë'), ('i', '\u{308}', 'ï'), ('o', '\u{308}', 'ö'), ('u', '\u{308}', 'ü'), ('y', '\u{308}', 'ÿ'),
+        ('A', '\u{308}', 'Ä'), ('E', '\u{308}', 'Ë'), ('O', '\u{308}', 'Ö'), ('U', '\u{308}', 'Ü'),
+        ('a', '\u{303}', 'ã'), ('n', '\u{303}', 'ñ'), ('o', '\u{303}', 'õ'), ('N', '\u{303}', 'Ñ'),
+        ('c', '\u{327}', 'ç'), ('C', '\u{327}', 'Ç'),
+        ('c', '\u{30C}', 'č'), ('s', '\u{30C}', 'š'), ('z', '\u{30C}', 'ž'), ('r', '\u{30C}', 'ř'), ('e', '\u{30C}', 'ě'),
+        ('C', '\u{30C}', 'Č'), ('S', '\u{30C}', 'Š'), ('Z', '\u{30C}', 'Ž'),
+        ('a', '\u{30A}', 'å'), ('A', '\u{30A}', 'Å'), ('u', '\u{30A}', 'ů'),
+        ('o', '\u{30B}', 'ő'), ('u', '\u{30B}', 'ű'),
+        ('g', '\u{306}', 'ğ'), ('a', '\u{306}', 'ă'),
+        ('a', '\u{328}', 'ą'), ('e', '\u{328}', 'ę'),
+        ('z', '\u{307}', 'ż'), ('I', '\u{307}', 'İ'),
+    ];
+
+    let mut out = String::new();
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        if let Some(&next) = chars.peek()
+            && let Some((_, _, composed)) = TABLE.iter().find(|(base, accent, _)| *base == c && *accent == next)
+        {
+            out.push(*composed);
+            chars.next();
+            continue;
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Write the nodes of `map` as BibTeX. When `node_ids` is given only those
+/// nodes are exported.
+pub fn export(map: &MindMap, node_ids: Option<&[Uuid]>) -> String {
+    let mut out = String::new();
+
+    for metadata in references::collect(map, node_ids) {
+        let entry_type = references::entry_type(&metadata);
+
+        let mut fields: Vec<(&str, String)> = Vec::new();
+        fields.push(("title", escape(&metadata.title)));
+        if !metadata.authors.is_empty() {
+            let authors: Vec<String> = metadata.author_names().iter().map(format_name).collect();
+            fields.push(("author", authors.join(" and ")));
+        }
+        if let Some(year) = references::year(&metadata.date) {
+            fields.push(("year", year));
+        }
+        if let Some(venue) = &metadata.venue {
+            let field = match entry_type.as_str() {
+                "article" => "journal",
+                "inproceedings" | "incollection" | "inbook" => "booktitle",
+                "phdthesis" | "mastersthesis" => "school",
+                "book" => "publisher",
+                _ => "howpublished",
+            };
+            fields.push((field, escape(venue)));
+        }
+        if let Some(doi) = &metadata.doi {
+            fields.push(("doi", doi.clone()));
+        }
+        if let Some(arxiv) = &metadata.arxiv {
+            fields.push(("eprint", arxiv.clone()));
+            fields.push(("archiveprefix", "arXiv".to_string()));
+        }
+        if !metadata.keywords.is_empty() {
+            fields.push(("keywords", escape(&metadata.keywords.join(", "))));
+        }
+
+        out.push_str(&format!("@{}{{{},\n", entry_type, metadata.citation_key.unwrap_or_default()));
+        for (name, value) in fields {
+            out.push_str(&format!("  {} = {{{}}},\n", name, value));
+        }
+        out.push_str("}\n\n");
+    }
+
+    out
+}
+
+// "Jonathan Oostvogels" -> "Oostvogels, Jonathan"
+fn format_name(name: &PersonName) -> String {
+    if name.given.is_empty() {
+        format!("{{{}}}", escape(&name.family))
+    } else {
+        format!("{}, {}", escape(&name.family), escape(&name.given))
+    }
+}
+
+/// Escape characters that have a special meaning in LaTeX.
+pub fn escape(value: &str) -> String {
+    let mut out = String::new();
+    for c in value.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '\\' => out.push_str("\\textbackslash{}"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,264 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+use crate::core::map::{MindMap, Node};
+use crate::core::search::extract_pages;
+
+// Headings that start the bibliography, compared after stripping numbering
+const HEADINGS: [&str; 5] = ["references", "bibliography", "works cited", "literature cited", "cited literature"];
+// Headings that end it again
+const END_HEADINGS: [&str; 4] = ["appendix", "appendices", "supplementary material", "acknowledgments"];
+// Shortest text that is kept as a reference entry
+const MIN_ENTRY_LENGTH: usize = 20;
+// Matches scoring below this are not proposed
+const MIN_SCORE: f32 = 0.75;
+// Shorter titles, such as "Attention", match too many entries by chance
+const MIN_TITLE_LENGTH: usize = 12;
+// Weight of the author match when the node has authors, the rest is the title
+const AUTHOR_WEIGHT: f32 = 0.25;
+
+/// A References edge from the paper being scanned to a node its bibliography
+/// appears to cite.
+#[derive(Debug, Clone)]
+pub struct LinkProposal {
+    pub from: Uuid,        // citing node
+    pub to: Uuid,          // cited node
+    pub reference: String, // the bibliography entry that matched
+    pub score: f32,        // 0..1, how closely the entry matches the node
+}
+
+/// Read the bibliography of a PDF, one string per entry.
+pub fn extract_references(path: &Path) -> Result<Vec<String>> {
+    let text = extract_pages(path)?.join("\n");
+    let section = reference_section(&text).ok_or_else(|| anyhow!("no reference section found"))?;
+    Ok(split_entries(section))
+}
+
+/// Extract the bibliography of `path` on a background thread.
+pub fn spawn_extractor(path: PathBuf) -> Receiver<Result<Vec<String>>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = sender.send(extract_references(&path));
+    });
+    receiver
+}
+
+/// Match the bibliography entries of `citing` against every other node and
+/// propose a References edge for each node that is cited, best matches first.
+/// Nodes that `citing` already has an edge to are skipped.
+pub fn propose_links(map: &MindMap, citing: Uuid, references: &[String]) -> Vec<LinkProposal> {
+    let texts: Vec<String> = references.iter().map(|r| letters(r)).collect();
+    let grams: Vec<HashSet<&str>> = texts.iter().map(|t| trigrams(t)).collect();
+    let linked: HashSet<Uuid> = map.edges.iter()
+        .filter(|e| e.from == citing)
+        .map(|e| e.to)
+        .collect();
+
+    let mut proposals: Vec<LinkProposal> = map.nodes.iter()
+        .filter(|n| n.id != citing && !linked.contains(&n.id))
+        .filter_map(|node| {
+            let (reference, score) = references.iter()
+                .zip(texts.iter().zip(&grams))
+                .filter_map(|(reference, (text, grams))| Some((reference, match_score(node, text, grams)?)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))?;
+            (score >= MIN_SCORE).then(|| LinkProposal { from: citing, to: node.id, reference: reference.clone(), score })
+        })
+        .collect();
+
+    proposals.sort_by(|a, b| b.score.total_cmp(&a.score));
+    proposals
+}
+
+// How well a bibliography entry matches a node: the share of the title's
+// letter trigrams found in the entry, blended with the share of the first few
+// authors whose family name appears. Text extracted from PDFs often loses the
+// spaces between words, so both sides are compared without any. None when the
+// node has no usable title.
+fn match_score(node: &Node, reference: &str, reference_grams: &HashSet<&str>) -> Option<f32> {
+    let metadata = node.metadata.as_ref();
+    let title = metadata.map(|m| m.title.as_str()).filter(|t| !t.is_empty()).unwrap_or(&node.title);
+    let title_text = letters(title);
+    if title_text.len() < MIN_TITLE_LENGTH {
+        return None;
+    }
+    let title_grams = trigrams(&title_text);
+    let found = title_grams.iter().filter(|g| reference_grams.contains(*g)).count();
+    let title_score = found as f32 / title_grams.len() as f32;
+
+    let authors: Vec<String> = metadata
+        .map(|m| m.author_names())
+        .unwrap_or_default()
+        .iter()
+        .take(3)
+        .map(|name| letters(&name.family))
+        .filter(|name| !name.is_empty())
+        .collect();
+    if authors.is_empty() {
+        return Some(title_score);
+    }
+
+    let found = authors.iter().filter(|a| reference.contains(a.as_str())).count();
+    let author_score = found as f32 / authors.len() as f32;
+    Some(title_score * (1.0 - AUTHOR_WEIGHT) + author_score * AUTHOR_WEIGHT)
+}
+
+// Lowercase letters and digits only, "Deep Residual-Learning" -> "deepresiduallearning"
+pub(crate) fn letters(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+pub(crate) fn trigrams(text: &str) -> HashSet<&str> {
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).chain([text.len()]).collect();
+    boundaries.windows(4).map(|w| &text[w[0]..w[3]]).collect()
+}
+
+// The text after the last bibliography heading, up to an appendix if one follows.
+// Headings are looked for on lines of their own first; some PDFs extract to
+// long runs of text, so a capitalised heading inside a line is accepted too.
+fn reference_section(text: &str) -> Option<&str> {
+    let mut start = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if HEADINGS.contains(&heading(line).as_str()) {
+            start = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    let start = start.or_else(|| inline_heading(text))?;
+    let section = &text[start..];
+
+    let mut end = section.len();
+    let mut offset = 0;
+    for line in section.split_inclusive('\n') {
+        if END_HEADINGS.contains(&heading(line).as_str()) {
+            end = offset;
+            break;
+        }
+        offset += line.len();
+    }
+    Some(&section[..end])
+}
+
+// End of the last "REFERENCES" or "References" that is a word of its own
+fn inline_heading(text: &str) -> Option<usize> {
+    HEADINGS.iter()
+        .flat_map(|heading| {
+            let mut capitalised = heading[..1].to_uppercase();
+            capitalised.push_str(&heading[1..]);
+            [heading.to_uppercase(), capitalised]
+        })
+        .filter_map(|heading| {
+            text.match_indices(&heading)
+                .map(|(i, _)| i + heading.len())
+                .filter(|end| !text[*end..].starts_with(|c: char| c.is_lowercase()))
+                .last()
+        })
+        .max()
+}
+
+// "7. REFERENCES" or "VII References" -> "references"
+fn heading(line: &str) -> String {
+    let line = line.trim();
+    if line.len() > 40 {
+        return String::new();
+    }
+    let text = match line.split_once(char::is_whitespace) {
+        Some((number, rest)) if is_section_number(number) => rest,
+        _ => line,
+    };
+    text.trim().trim_end_matches(':').to_lowercase()
+}
+
+fn is_section_number(word: &str) -> bool {
+    let word = word.trim_end_matches('.');
+    !word.is_empty() && (word.chars().all(|c| c.is_ascii_digit() || c == '.') || word.chars().all(|c| "IVXLC".contains(c)))
+}
+
+// Split a bibliography into entries. Numbered styles ("[12]" or "12.") are
+// split on the numbers, which must count up from 1; author-year styles are
+// split where a line ends a sentence and the next starts with a name.
+fn split_entries(section: &str) -> Vec<String> {
+    let entries = split_numbered(section, |n| format!("[{}]", n), false)
+        .or_else(|| split_numbered(section, |n| format!("{}.", n), true))
+        .unwrap_or_else(|| split_author_year(section));
+
+    entries.iter()
+        .map(|entry| clean(entry))
+        .filter(|entry| entry.len() >= MIN_ENTRY_LENGTH)
+        .collect()
+}
+
+fn split_numbered(section: &str, label: impl Fn(usize) -> String, line_start: bool) -> Option<Vec<&str>> {
+    let mut starts = Vec::new();
+    let mut from = 0;
+    loop {
+        let label = label(starts.len() + 1);
+        let found = section[from..].match_indices(&label)
+            .map(|(i, _)| from + i)
+            .find(|i| !line_start || section[..*i].ends_with('\n') || *i == 0);
+        match found {
+            Some(i) => {
+                starts.push((i, i + label.len()));
+                from = i + label.len();
+            }
+            None => break,
+        }
+    }
+
+    // A single "[1]" is more likely a stray citation than a numbered list
+    if starts.len() < 2 {
+        return None;
+    }
+    let entries = starts.iter()
+        .enumerate()
+        .map(|(k, (_, text_start))| {
+            let end = starts.get(k + 1).map_or(section.len(), |(next, _)| *next);
+            &section[*text_start..end]
+        })
+        .collect();
+    Some(entries)
+}
+
+fn split_author_year(section: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut previous_ends_sentence = false;
+    let mut offset = 0;
+    for line in section.split_inclusive('\n') {
+        let trimmed = line.trim();
+        // "Vaswani, A., Shazeer, N., ..." starts a new entry after a full stop
+        let starts_name = trimmed.chars().next().is_some_and(char::is_uppercase)
+            && trimmed.find(',').is_some_and(|i| i < 40);
+        if previous_ends_sentence && starts_name && offset > start {
+            entries.push(&section[start..offset]);
+            start = offset;
+        }
+        if !trimmed.is_empty() {
+            previous_ends_sentence = trimmed.ends_with('.');
+        }
+        offset += line.len();
+    }
+    entries.push(&section[start..]);
+    entries
+}
+
+// Join the lines of an entry, undoing hyphenation at line breaks
+fn clean(entry: &str) -> String {
+    let mut text = String::new();
+    for line in entry.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let joins_word = text.ends_with('-') && line.chars().next().is_some_and(char::is_lowercase);
+        if joins_word {
+            text.pop();
+        } else if !text.is_empty() {
+            text.push(' ');
+        }
+        text.push_str(line);
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
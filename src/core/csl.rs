@@ -0,0 +1,238 @@
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::core::identifiers;
+use crate::core::map::MindMap;
+use crate::core::pdfparser::{Metadata, PersonName};
+use crate::core::references::{self, Reference};
+
+// CSL type <-> BibTeX entry type, the first match wins on export
+const TYPES: [(&str, &str); 9] = [
+    ("article-journal", "article"),
+    ("paper-conference", "inproceedings"),
+    ("chapter", "incollection"),
+    ("book", "book"),
+    ("thesis", "phdthesis"),
+    ("report", "techreport"),
+    ("webpage", "online"),
+    ("manuscript", "unpublished"),
+    ("document", "misc"),
+];
+
+/// One item of a CSL-JSON array, as written by Zotero, Mendeley or pandoc.
+/// Only the variables that map onto node metadata are read.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CslItem {
+    #[serde(default)]
+    pub id: CslId,
+    #[serde(rename = "type", default)]
+    pub item_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub author: Vec<CslName>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub editor: Vec<CslName>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued: Option<CslDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher: Option<String>,
+    #[serde(rename = "DOI", default, skip_serializing_if = "Option::is_none")]
+    pub doi: Option<String>,
+    #[serde(rename = "URL", default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyword: Option<String>, // comma separated
+}
+
+// Ids are strings in practice, but numbers are valid CSL too
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CslId {
+    Text(String),
+    Number(i64),
+}
+
+impl Default for CslId {
+    fn default() -> Self {
+        CslId::Text(String::new())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CslName {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub given: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub literal: Option<String>, // institutions and names that cannot be split
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CslDate {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub date_parts: Vec<Vec<serde_json::Value>>, // [[year, month, day]], parts may be strings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+impl CslDate {
+    // [[2023, 1, 15]] -> "2023-01-15"
+    fn to_date(&self) -> String {
+        let parts: Vec<u32> = self.date_parts.first()
+            .map(|parts| {
+                parts.iter()
+                    .map_while(|p| p.as_u64().map(|n| n as u32).or_else(|| p.as_str()?.trim().parse().ok()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        match parts.as_slice() {
+            [year, month, day, ..] => format!("{}-{:02}-{:02}", year, month, day),
+            [year, month] => format!("{}-{:02}", year, month),
+            [year] => year.to_string(),
+            _ => self.raw.clone().unwrap_or_default(),
+        }
+    }
+
+    fn from_date(date: &str) -> Option<CslDate> {
+        let parts: Vec<serde_json::Value> = date.split('-')
+            .take(3)
+            .map_while(|p| p.parse::<u32>().ok())
+            .map(serde_json::Value::from)
+            .collect();
+        if parts.is_empty() {
+            return (!date.is_empty()).then(|| CslDate { date_parts: Vec::new(), raw: Some(date.to_string()) });
+        }
+        Some(CslDate { date_parts: vec![parts], raw: None })
+    }
+}
+
+impl CslItem {
+    /// The item's id, None when it has none.
+    pub fn key(&self) -> Option<String> {
+        match &self.id {
+            CslId::Text(id) if id.trim().is_empty() => None,
+            CslId::Text(id) => Some(id.clone()),
+            CslId::Number(id) => Some(id.to_string()),
+        }
+    }
+
+    pub fn to_metadata(&self) -> Metadata {
+        let entry_type = TYPES.iter()
+            .find(|(csl, _)| *csl == self.item_type)
+            .map(|(_, bib)| bib.to_string())
+            .unwrap_or_else(|| "misc".to_string());
+
+        let names = if self.author.is_empty() { &self.editor } else { &self.author };
+        let names = names.iter()
+            .map(|name| match &name.literal {
+                Some(literal) => PersonName { given: String::new(), family: literal.clone() },
+                None => PersonName {
+                    given: name.given.clone().unwrap_or_default(),
+                    family: name.family.clone().unwrap_or_default(),
+                },
+            })
+            .filter(|name| !name.family.is_empty() || !name.given.is_empty())
+            .collect();
+
+        let keywords = self.keyword.as_deref()
+            .map(|k| k.split([',', ';']).map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+
+        let mut metadata = Metadata {
+            title: self.title.clone().unwrap_or_default(),
+            keywords,
+            date: self.issued.as_ref().map(CslDate::to_date).unwrap_or_default(),
+            doi: self.doi.clone(),
+            arxiv: self.url.as_deref().and_then(identifiers::find_arxiv),
+            venue: self.container_title.clone().or_else(|| self.publisher.clone()),
+            entry_type: Some(entry_type),
+            ..Default::default()
+        };
+        metadata.set_authors(names);
+
+        let citation_key = self.key().unwrap_or_else(|| references::generate_key(&metadata));
+        if metadata.title.is_empty() {
+            metadata.title.clone_from(&citation_key);
+        }
+        metadata.citation_key = Some(citation_key);
+        metadata
+    }
+
+    pub fn from_metadata(metadata: &Metadata) -> CslItem {
+        let entry_type = references::entry_type(metadata);
+        let item_type = TYPES.iter()
+            .find(|(_, bib)| *bib == entry_type)
+            .map(|(csl, _)| csl.to_string())
+            .unwrap_or_else(|| "document".to_string());
+
+        let author = metadata.author_names()
+            .into_iter()
+            .map(|name| {
+                if name.given.is_empty() {
+                    CslName { literal: Some(name.family), ..Default::default() }
+                } else {
+                    CslName { family: Some(name.family), given: Some(name.given), literal: None }
+                }
+            })
+            .collect();
+
+        // Books are published by a publisher rather than contained in something
+        let (container_title, publisher) = match entry_type.as_str() {
+            "book" | "techreport" | "phdthesis" => (None, metadata.venue.clone()),
+            _ => (metadata.venue.clone(), None),
+        };
+
+        CslItem {
+            id: CslId::Text(metadata.citation_key.clone().unwrap_or_default()),
+            item_type,
+            title: Some(metadata.title.clone()),
+            author,
+            editor: Vec::new(),
+            issued: CslDate::from_date(&metadata.date),
+            container_title,
+            publisher,
+            doi: metadata.doi.clone(),
+            url: metadata.arxiv.as_deref().map(identifiers::arxiv_url),
+            keyword: (!metadata.keywords.is_empty()).then(|| metadata.keywords.join(", ")),
+        }
+    }
+}
+
+/// Parse a CSL-JSON file, which is either an array of items or a single item.
+pub fn parse(input: &str) -> Result<Vec<CslItem>> {
+    let value: serde_json::Value = serde_json::from_str(input)?;
+    let items = match value {
+        serde_json::Value::Array(_) => serde_json::from_value(value)?,
+        _ => vec![serde_json::from_value(value)?],
+    };
+    Ok(items)
+}
+
+/// Read a CSL-JSON file as references. CSL has no variable for local files, so
+/// no PDFs are attached.
+pub fn import_file(path: &Path) -> Result<Vec<Reference>> {
+    let references = parse(&fs::read_to_string(path)?)?
+        .iter()
+        .map(|item| Reference { metadata: item.to_metadata(), pdf: None })
+        .collect();
+    Ok(references)
+}
+
+/// Write the nodes of `map` as a CSL-JSON array. When `node_ids` is given only
+/// those nodes are exported.
+pub fn export(map: &MindMap, node_ids: Option<&[Uuid]>) -> Result<String> {
+    let items: Vec<CslItem> = references::collect(map, node_ids)
+        .iter()
+        .map(CslItem::from_metadata)
+        .collect();
+    Ok(serde_json::to_string_pretty(&items)?)
+}
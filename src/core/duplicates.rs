@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use uuid::Uuid;
+use crate::core::citations::{letters, trigrams};
+use crate::core::identifiers;
+use crate::core::map::{MindMap, Node};
+use crate::core::undo::Command;
+
+// Share of letter trigrams two titles must have in common to count as the same
+const TITLE_SIMILARITY: f32 = 0.9;
+// Shorter titles are only compared for equality
+const MIN_TITLE_LENGTH: usize = 12;
+
+/// Why nodes were grouped as duplicates, strongest evidence first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Evidence {
+    SamePdf,
+    SameIdentifier,
+    SimilarTitle,
+}
+
+impl Evidence {
+    pub fn name(self) -> &'static str {
+        match self {
+            Evidence::SamePdf => "Identical PDF",
+            Evidence::SameIdentifier => "Same DOI or arXiv ID",
+            Evidence::SimilarTitle => "Near-identical title",
+        }
+    }
+}
+
+/// Nodes that appear to be the same paper.
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub nodes: Vec<Uuid>, // in map order
+    pub evidence: Evidence,
+}
+
+/// Group the nodes of `map` that share a PDF (compared by content), a DOI or
+/// arXiv id, or a near-identical title. Groups are joined transitively, so a
+/// node with the same DOI as one node and the same title as another ends up in
+/// one group with both.
+pub fn find_duplicates(map: &MindMap) -> Vec<DuplicateGroup> {
+    let mut groups = Groups::new(map.nodes.len());
+
+    for (a, b) in same_pdf(&map.nodes) {
+        groups.join(a, b, Evidence::SamePdf);
+    }
+
+    let mut by_identifier: HashMap<String, usize> = HashMap::new();
+    for (i, node) in map.nodes.iter().enumerate() {
+        let Some(metadata) = &node.metadata else { continue };
+        let keys = metadata.doi.iter().map(|doi| format!("doi:{}", identifiers::normalize_doi(doi)))
+            .chain(metadata.arxiv.iter().map(|id| format!("arxiv:{}", id)));
+        for key in keys {
+            match by_identifier.get(&key) {
+                Some(&first) => groups.join(first, i, Evidence::SameIdentifier),
+                None => {
+                    by_identifier.insert(key, i);
+                }
+            }
+        }
+    }
+
+    let titles: Vec<String> = map.nodes.iter().map(|n| letters(title(n))).collect();
+    let grams: Vec<_> = titles.iter().map(|t| trigrams(t)).collect();
+    for a in 0..titles.len() {
+        for b in a + 1..titles.len() {
+            let similar = if titles[a].len() < MIN_TITLE_LENGTH || titles[b].len() < MIN_TITLE_LENGTH {
+                !titles[a].is_empty() && titles[a] == titles[b]
+            } else {
+                let common = grams[a].intersection(&grams[b]).count();
+                2.0 * common as f32 / (grams[a].len() + grams[b].len()) as f32 >= TITLE_SIMILARITY
+            };
+            if similar {
+                groups.join(a, b, Evidence::SimilarTitle);
+            }
+        }
+    }
+
+    groups.collect(map)
+}
+
+// Metadata titles are what the paper is called; node titles may be file names
+fn title(node: &Node) -> &str {
+    node.metadata.as_ref()
+        .map(|m| m.title.as_str())
+        .filter(|t| !t.is_empty())
+        .unwrap_or(&node.title)
+}
+
+// Pairs of nodes whose PDFs have the same content. Only files of equal size
+// are read and hashed.
+fn same_pdf(nodes: &[Node]) -> Vec<(usize, usize)> {
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some(size) = node.path.as_ref().and_then(|p| fs::metadata(p).ok()).map(|m| m.len()) {
+            by_size.entry(size).or_default().push(i);
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for candidates in by_size.values().filter(|c| c.len() > 1) {
+        let mut by_hash: HashMap<u64, usize> = HashMap::new();
+        for &i in candidates {
+            let Some(hash) = nodes[i].path.as_ref().and_then(|p| fs::read(p).ok()).map(|bytes| {
+                let mut hasher = DefaultHasher::new();
+                bytes.hash(&mut hasher);
+                hasher.finish()
+            }) else { continue };
+            match by_hash.get(&hash) {
+                Some(&first) => pairs.push((first, i)),
+                None => {
+                    by_hash.insert(hash, i);
+                }
+            }
+        }
+    }
+    pairs
+}
+
+// Union-find over node indices that remembers the strongest evidence per group
+struct Groups {
+    parent: Vec<usize>,
+    evidence: Vec<Option<Evidence>>,
+}
+
+impl Groups {
+    fn new(len: usize) -> Groups {
+        Groups { parent: (0..len).collect(), evidence: vec![None; len] }
+    }
+
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: usize, b: usize, evidence: Evidence) {
+        let (a, b) = (self.root(a), self.root(b));
+        let strongest = [self.evidence[a], self.evidence[b], Some(evidence)].into_iter().flatten().min();
+        self.parent[b] = a;
+        self.evidence[a] = strongest;
+    }
+
+    fn collect(mut self, map: &MindMap) -> Vec<DuplicateGroup> {
+        let mut members: HashMap<usize, Vec<Uuid>> = HashMap::new();
+        let mut roots = Vec::new();
+        for (i, node) in map.nodes.iter().enumerate() {
+            let root = self.root(i);
+            let nodes = members.entry(root).or_default();
+            if nodes.is_empty() {
+                roots.push(root);
+            }
+            nodes.push(node.id);
+        }
+
+        roots.into_iter()
+            .filter_map(|root| {
+                let nodes = members.remove(&root)?;
+                let evidence = self.evidence[root]?;
+                (nodes.len() > 1).then_some(DuplicateGroup { nodes, evidence })
+            })
+            .collect()
+    }
+}
+
+/// The node of a group that is kept by default: the one with a PDF and the
+/// most annotations, tags and edges.
+pub fn default_survivor(map: &MindMap, group: &DuplicateGroup) -> Uuid {
+    group.nodes.iter()
+        .filter_map(|id| map.nodes.iter().find(|n| n.id == *id))
+        .max_by_key(|node| {
+            let edges = map.edges.iter().filter(|e| e.from == node.id || e.to == node.id).count();
+            (node.path.is_some(), node.annotations.len() + node.tags.len() + edges)
+        })
+        .map_or(group.nodes[0], |node| node.id)
+}
+
+/// Build the command that merges `others` into `survivor`. The survivor gains
+/// their annotations and tags, and metadata fields it lacks. Their edges are
+/// rewired to the survivor; edges that would then join the survivor to itself
+/// or repeat an existing edge are removed, and their annotations moved to the
+/// edge that is kept. Finally the merged nodes are removed.
+pub fn merge_command(map: &MindMap, survivor: Uuid, others: &[Uuid]) -> Option<Command> {
+    let mut scratch = map.clone();
+    let mut commands = Vec::new();
+    // Each command is built against the map as left by the previous ones, so
+    // the batch reverts cleanly in reverse order
+    let mut push = |scratch: &mut MindMap, command: Command| {
+        command.apply(scratch);
+        commands.push(command);
+    };
+
+    let others: Vec<Uuid> = others.iter().copied().filter(|id| *id != survivor).collect();
+    let survivor_before = map.nodes.iter().find(|n| n.id == survivor)?.clone();
+    let mut survivor_after = survivor_before.clone();
+
+    let edge_ids: Vec<Uuid> = map.edges.iter()
+        .filter(|e| others.contains(&e.from) || others.contains(&e.to))
+        .map(|e| e.id)
+        .collect();
+    for edge_id in edge_ids {
+        let Some(before) = scratch.edges.iter().find(|e| e.id == edge_id).cloned() else { continue };
+        let mut after = before.clone();
+        if others.contains(&after.from) {
+            after.from = survivor;
+        }
+        if others.contains(&after.to) {
+            after.to = survivor;
+        }
+
+        let existing = scratch.edges.iter()
+            .find(|e| e.id != edge_id && e.from == after.from && e.to == after.to && e.edge_type == after.edge_type)
+            .cloned();
+        if after.from == after.to || existing.is_some() {
+            if let Some(kept) = existing && !before.annotations.is_empty() {
+                let mut merged = kept.clone();
+                merged.annotations.extend(before.annotations.iter().cloned());
+                push(&mut scratch, Command::UpdateEdge { before: kept, after: merged });
+            }
+            if let Some(command) = Command::remove_edge(&scratch, edge_id) {
+                push(&mut scratch, command);
+            }
+        } else {
+            push(&mut scratch, Command::UpdateEdge { before, after });
+        }
+    }
+
+    for other in &others {
+        let Some(node) = map.nodes.iter().find(|n| n.id == *other) else { continue };
+        for annotation in &node.annotations {
+            if !survivor_after.annotations.iter().any(|a| a.id == annotation.id) {
+                survivor_after.annotations.push(annotation.clone());
+            }
+        }
+        for tag in &node.tags {
+            if !survivor_after.tags.contains(tag) {
+                survivor_after.tags.push(*tag);
+            }
+        }
+        if survivor_after.path.is_none() {
+            survivor_after.path.clone_from(&node.path);
+        }
+        survivor_after.metadata = match (survivor_after.metadata.take(), &node.metadata) {
+            (Some(mut kept), Some(other)) => {
+                kept.doi = kept.doi.or_else(|| other.doi.clone());
+                kept.arxiv = kept.arxiv.or_else(|| other.arxiv.clone());
+                kept.venue = kept.venue.or_else(|| other.venue.clone());
+                kept.citation_key = kept.citation_key.or_else(|| other.citation_key.clone());
+                kept.entry_type = kept.entry_type.or_else(|| other.entry_type.clone());
+                if kept.date.is_empty() {
+                    kept.date.clone_from(&other.date);
+                }
+                if kept.path.is_empty() {
+                    kept.path.clone_from(&other.path);
+                }
+                if kept.authors.is_empty() {
+                    kept.authors.clone_from(&other.authors);
+                    kept.author_parts.clone_from(&other.author_parts);
+                }
+                for keyword in &other.keywords {
+                    if !kept.keywords.contains(keyword) {
+                        kept.keywords.push(keyword.clone());
+                    }
+                }
+                Some(kept)
+            }
+            (kept, other) => kept.or_else(|| other.clone()),
+        };
+    }
+    push(&mut scratch, Command::UpdateNode { before: survivor_before, after: survivor_after });
+
+    for other in &others {
+        if let Some(command) = Command::remove_node(&scratch, *other) {
+            push(&mut scratch, command);
+        }
+    }
+
+    Some(Command::Batch(commands))
+}
+
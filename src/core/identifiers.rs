@@ -0,0 +1,88 @@
+// Characters that end a DOI found in running text
+const DOI_TERMINATORS: [char; 5] = ['"', '\'', '<', '>', '\u{a0}'];
+// Punctuation that belongs to the sentence rather than to an identifier
+const TRAILING_PUNCTUATION: [char; 6] = ['.', ',', ';', ':', ']', '}'];
+
+/// The first DOI in `text`, e.g. "10.1145/3384419.3430718". Prefixes such as
+/// "doi:" or "https://doi.org/" are not part of the result.
+pub fn find_doi(text: &str) -> Option<String> {
+    text.match_indices("10.").find_map(|(start, _)| {
+        // "210.5/x" is a number, not a DOI
+        if text[..start].chars().next_back().is_some_and(|c| c.is_alphanumeric()) {
+            return None;
+        }
+
+        let rest = &text[start + 3..];
+        let registrant_end = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let registrant = &rest[..registrant_end];
+        if registrant.len() < 4 || !rest[registrant_end..].starts_with('/') {
+            return None;
+        }
+
+        let suffix = &rest[registrant_end + 1..];
+        let suffix_end = suffix.find(|c: char| c.is_whitespace() || DOI_TERMINATORS.contains(&c)).unwrap_or(suffix.len());
+        let suffix = trim_suffix(&suffix[..suffix_end]);
+        (!suffix.is_empty()).then(|| format!("10.{}/{}", registrant, suffix))
+    })
+}
+
+// Drop trailing punctuation, and closing parentheses that were not opened in
+// the DOI itself: "(doi:10.1000/xyz)." -> "10.1000/xyz"
+fn trim_suffix(suffix: &str) -> &str {
+    let mut suffix = suffix;
+    loop {
+        let trimmed = suffix.trim_end_matches(TRAILING_PUNCTUATION);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(inner) if inner.matches('(').count() <= inner.matches(')').count() => inner,
+            _ => trimmed,
+        };
+        if trimmed.len() == suffix.len() {
+            return suffix;
+        }
+        suffix = trimmed;
+    }
+}
+
+/// The first arXiv identifier in `text`, without its version: "arXiv:2001.08557v2"
+/// and "arxiv.org/abs/2001.08557" both give "2001.08557". Old-style identifiers
+/// such as "hep-th/9901001" are recognised too, as are arXiv DOIs.
+pub fn find_arxiv(text: &str) -> Option<String> {
+    // ASCII lowercasing keeps byte offsets, so positions carry over to `text`
+    let lower = text.to_ascii_lowercase();
+    lower.match_indices("arxiv").find_map(|(start, _)| {
+        let rest = &text[start + 5..];
+        let rest = [".org/abs/", ".org/pdf/", ":", ".", " "].iter()
+            .find_map(|prefix| rest.strip_prefix(prefix))
+            .unwrap_or(rest)
+            .trim_start();
+        new_style_arxiv(rest).or_else(|| old_style_arxiv(rest))
+    })
+}
+
+// "2001.08557v2" -> "2001.08557"
+fn new_style_arxiv(text: &str) -> Option<String> {
+    let (year_month, rest) = text.split_at_checked(4)?;
+    let number: String = rest.strip_prefix('.')?.chars().take_while(char::is_ascii_digit).collect();
+    let valid = year_month.chars().all(|c| c.is_ascii_digit()) && (4..=5).contains(&number.len());
+    valid.then(|| format!("{}.{}", year_month, number))
+}
+
+// "hep-th/9901001v1" or "math.GT/0309136" -> without the version
+fn old_style_arxiv(text: &str) -> Option<String> {
+    let (archive, rest) = text.split_once('/')?;
+    let valid_archive = !archive.is_empty()
+        && archive.len() <= 16
+        && archive.chars().all(|c| c.is_ascii_alphabetic() || c == '-' || c == '.');
+    let number: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    (valid_archive && number.len() == 7).then(|| format!("{}/{}", archive, number))
+}
+
+/// DOIs are case-insensitive, compare them in this form.
+pub fn normalize_doi(doi: &str) -> String {
+    find_doi(doi).unwrap_or_else(|| doi.trim().to_string()).to_lowercase()
+}
+
+pub fn arxiv_url(id: &str) -> String {
+    format!("https://arxiv.org/abs/{}", id)
+}
+
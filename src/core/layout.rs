@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+use crate::core::map::{EdgeType, MindMap};
+
+// Preferred gap between the borders of two connected nodes
+const IDEAL_GAP: f32 = 120.0;
+// Pull towards the centre of the laid out nodes, keeps components together
+const GRAVITY: f32 = 0.005;
+// Per-iteration cooling of the maximum step size
+const COOLING: f32 = 0.96;
+const MAX_ITERATIONS: usize = 400;
+// The layout has settled once no node moves further than this in a step
+const MIN_MOVEMENT: f32 = 0.5;
+
+/// Spring-electrical layout (Fruchterman–Reingold) that is advanced a few
+/// iterations per frame so the movement can be animated.
+///
+/// Nodes repel each other and edges pull their ends together. Distances are
+/// measured between node borders rather than centres, so wide nodes don't
+/// overlap. Nodes that are pinned, or outside the selection when laying out
+/// only the selection, keep their position but still push and pull the others.
+#[derive(Debug, Clone)]
+pub struct ForceLayout {
+    ids: Vec<Uuid>,
+    index: HashMap<Uuid, usize>,
+    start: Vec<[f32; 2]>,
+    positions: Vec<[f32; 2]>,
+    half_sizes: Vec<[f32; 2]>,
+    movable: Vec<bool>,
+    edges: Vec<(usize, usize)>,
+    center: [f32; 2],
+    temperature: f32,
+    iteration: usize,
+    finished: bool,
+}
+
+impl ForceLayout {
+    /// Prepare a layout of `map`. `sizes` holds the unzoomed size of each node;
+    /// when `only` is given, only those nodes are moved.
+    pub fn new(map: &MindMap, sizes: &HashMap<Uuid, [f32; 2]>, only: Option<&[Uuid]>) -> ForceLayout {
+        let ids: Vec<Uuid> = map.nodes.iter().map(|n| n.id).collect();
+        let index: HashMap<Uuid, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        let start: Vec<[f32; 2]> = map.nodes.iter().map(|n| [n.x, n.y]).collect();
+
+        let movable: Vec<bool> = map.nodes.iter()
+            .map(|n| !n.pinned && only.is_none_or(|only| only.contains(&n.id)))
+            .collect();
+
+        let half_sizes = map.nodes.iter()
+            .map(|n| sizes.get(&n.id).map_or([100.0, 20.0], |s| [s[0] / 2.0, s[1] / 2.0]))
+            .collect();
+
+        let edges = map.edges.iter()
+            .filter_map(|e| Some((*index.get(&e.from)?, *index.get(&e.to)?)))
+            .filter(|(a, b)| a != b)
+            .collect();
+
+        // Gravity pulls towards where the moving nodes are now, so laying out
+        // a selection doesn't drag it across the map
+        let moving: Vec<[f32; 2]> = start.iter().zip(&movable).filter(|(_, m)| **m).map(|(p, _)| *p).collect();
+        let center = if moving.is_empty() {
+            [0.0, 0.0]
+        } else {
+            let n = moving.len() as f32;
+            [moving.iter().map(|p| p[0]).sum::<f32>() / n, moving.iter().map(|p| p[1]).sum::<f32>() / n]
+        };
+
+        let finished = moving.is_empty();
+        ForceLayout {
+            ids,
+            index,
+            positions: start.clone(),
+            start,
+            half_sizes,
+            movable,
+            edges,
+            center,
+            temperature: IDEAL_GAP * 2.0,
+            iteration: 0,
+            finished,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Advance the simulation by one iteration. Returns false once it has settled.
+    pub fn step(&mut self) -> bool {
+        if self.finished {
+            return false;
+        }
+
+        let n = self.positions.len();
+        let mut forces = vec![[0.0f32; 2]; n];
+        let k = IDEAL_GAP;
+
+        // Repulsion between every pair involving at least one moving node
+        for i in 0..n {
+            for j in (i + 1)..n {
+                if !self.movable[i] && !self.movable[j] {
+                    continue;
+                }
+                let (direction, gap) = self.separation(i, j);
+                let force = k * k / gap.max(k * 0.05);
+                forces[i][0] += direction[0] * force;
+                forces[i][1] += direction[1] * force;
+                forces[j][0] -= direction[0] * force;
+                forces[j][1] -= direction[1] * force;
+            }
+        }
+
+        // Attraction along edges once their ends are further apart than IDEAL_GAP
+        for &(a, b) in &self.edges {
+            let (direction, gap) = self.separation(a, b);
+            if gap <= 0.0 {
+                continue;
+            }
+            let force = gap * gap / k;
+            forces[a][0] -= direction[0] * force;
+            forces[a][1] -= direction[1] * force;
+            forces[b][0] += direction[0] * force;
+            forces[b][1] += direction[1] * force;
+        }
+
+        let mut max_movement: f32 = 0.0;
+        for ((p, force), movable) in self.positions.iter_mut().zip(&forces).zip(&self.movable) {
+            if !movable {
+                continue;
+            }
+            let force = [
+                force[0] - (p[0] - self.center[0]) * GRAVITY * k,
+                force[1] - (p[1] - self.center[1]) * GRAVITY * k,
+            ];
+            let length = (force[0] * force[0] + force[1] * force[1]).sqrt();
+            if length < f32::EPSILON {
+                continue;
+            }
+            let movement = length.min(self.temperature);
+            p[0] += force[0] / length * movement;
+            p[1] += force[1] / length * movement;
+            max_movement = max_movement.max(movement);
+        }
+
+        self.temperature *= COOLING;
+        self.iteration += 1;
+        self.finished = self.iteration >= MAX_ITERATIONS || max_movement < MIN_MOVEMENT;
+        !self.finished
+    }
+
+    // Unit vector from j to i and the distance between their borders along it
+    fn separation(&self, i: usize, j: usize) -> ([f32; 2], f32) {
+        let (a, b) = (self.positions[i], self.positions[j]);
+        let mut delta = [a[0] - b[0], a[1] - b[1]];
+        let mut distance = (delta[0] * delta[0] + delta[1] * delta[1]).sqrt();
+        if distance < 0.01 {
+            // Nodes on top of each other (e.g. several PDFs added at the origin):
+            // push them apart in a direction that differs per pair
+            let angle = (i * 7 + j * 13) as f32;
+            delta = [angle.cos(), angle.sin()];
+            distance = 1.0;
+        }
+        let direction = [delta[0] / distance, delta[1] / distance];
+
+        // Extent of each rectangle along the direction between the centres
+        let extent = |half: [f32; 2]| direction[0].abs() * half[0] + direction[1].abs() * half[1];
+        let gap = distance - extent(self.half_sizes[i]) - extent(self.half_sizes[j]);
+        (direction, gap)
+    }
+
+    /// Write the current positions into the map.
+    pub fn apply(&self, map: &mut MindMap) {
+        for node in &mut map.nodes {
+            if let Some(&i) = self.index.get(&node.id) && self.movable[i] {
+                node.x = self.positions[i][0];
+                node.y = self.positions[i][1];
+            }
+        }
+    }
+
+    /// (node, from, to) for every node that moved, for `Command::MoveNodes`.
+    pub fn moves(&self) -> Vec<(Uuid, [f32; 2], [f32; 2])> {
+        (0..self.ids.len())
+            .filter(|i| self.movable[*i] && self.start[*i] != self.positions[*i])
+            .map(|i| (self.ids[i], self.start[i], self.positions[i]))
+            .collect()
+    }
+}
+
+// Spacing of the layered layout
+const LAYER_GAP: f32 = 80.0;
+const NODE_GAP: f32 = 40.0;
+const DUMMY_WIDTH: f32 = 20.0;
+const ORDERING_SWEEPS: usize = 24;
+const POSITIONING_SWEEPS: usize = 8;
+// Nodes without References edges are put in rows of this many below the layers
+const LOOSE_COLUMNS: usize = 6;
+
+/// Sugiyama-style layered layout of the `References` edges, in the usual four
+/// phases: cycles are broken by reversing DFS back edges, nodes are assigned
+/// to layers by longest path, the order within each layer is chosen by
+/// barycenter sweeps to reduce crossings, and x positions are pulled towards
+/// connected nodes without letting neighbours overlap.
+///
+/// Cited papers end up above the papers citing them, or below when
+/// `cited_above` is false. Pinned nodes take part but are not moved. Returns
+/// the moves for `Command::MoveNodes`; the laid out map keeps its centre.
+pub fn layered(map: &MindMap, sizes: &HashMap<Uuid, [f32; 2]>, cited_above: bool) -> Vec<(Uuid, [f32; 2], [f32; 2])> {
+    let n = map.nodes.len();
+    let index: HashMap<Uuid, usize> = map.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+    let size = |i: usize| sizes.get(&map.nodes[i].id).copied().unwrap_or([200.0, 40.0]);
+
+    // Edges point from the cited paper to the paper citing it
+    let mut edges: Vec<(usize, usize)> = map.edges.iter()
+        .filter(|e| e.edge_type == EdgeType::REFERENCES)
+        .filter_map(|e| Some((*index.get(&e.to)?, *index.get(&e.from)?)))
+        .filter(|(a, b)| a != b)
+        .collect();
+    edges.sort_unstable();
+    edges.dedup();
+
+    let edges = remove_cycles(n, &edges);
+    let connected: Vec<bool> = (0..n).map(|i| edges.iter().any(|(a, b)| *a == i || *b == i)).collect();
+
+    // Longest-path layering in topological order
+    let mut layer_of = vec![0usize; n];
+    for v in topological_order(n, &edges) {
+        for &(a, b) in &edges {
+            if a == v {
+                layer_of[b] = layer_of[b].max(layer_of[a] + 1);
+            }
+        }
+    }
+
+    // Vertices are the connected nodes plus dummies on edges spanning several layers
+    let mut vertex_node: Vec<Option<usize>> = Vec::new();
+    let mut vertex_layer: Vec<usize> = Vec::new();
+    let mut vertex_of_node = vec![usize::MAX; n];
+    for i in (0..n).filter(|i| connected[*i]) {
+        vertex_of_node[i] = vertex_node.len();
+        vertex_node.push(Some(i));
+        vertex_layer.push(layer_of[i]);
+    }
+    let mut links: Vec<(usize, usize)> = Vec::new();
+    for &(a, b) in &edges {
+        let mut previous = vertex_of_node[a];
+        for layer in (layer_of[a] + 1)..layer_of[b] {
+            vertex_node.push(None);
+            vertex_layer.push(layer);
+            links.push((previous, vertex_node.len() - 1));
+            previous = vertex_node.len() - 1;
+        }
+        links.push((previous, vertex_of_node[b]));
+    }
+
+    let vertex_count = vertex_node.len();
+    let layer_count = vertex_layer.iter().max().map_or(0, |l| l + 1);
+    let mut upper: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    let mut lower: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for &(a, b) in &links {
+        lower[a].push(b);
+        upper[b].push(a);
+    }
+
+    let mut layers: Vec<Vec<usize>> = vec![Vec::new(); layer_count];
+    for v in 0..vertex_count {
+        layers[vertex_layer[v]].push(v);
+    }
+    let layers = order_layers(layers, &upper, &lower);
+
+    // x positions: pack each layer, then pull vertices towards their neighbours
+    let width = |v: usize| vertex_node[v].map_or(DUMMY_WIDTH, |i| size(i)[0]);
+    let mut x = vec![0.0f32; vertex_count];
+    for layer in &layers {
+        let total: f32 = layer.iter().map(|v| width(*v)).sum::<f32>() + NODE_GAP * layer.len().saturating_sub(1) as f32;
+        let mut left = -total / 2.0;
+        for &v in layer {
+            x[v] = left + width(v) / 2.0;
+            left += width(v) + NODE_GAP;
+        }
+    }
+    for sweep in 0..POSITIONING_SWEEPS {
+        let neighbours = if sweep % 2 == 0 { &upper } else { &lower };
+        for layer in &layers {
+            let desired: Vec<f32> = layer.iter()
+                .map(|v| match neighbours[*v].as_slice() {
+                    [] => x[*v],
+                    list => list.iter().map(|u| x[*u]).sum::<f32>() / list.len() as f32,
+                })
+                .collect();
+            let gaps: Vec<f32> = layer.windows(2).map(|w| (width(w[0]) + width(w[1])) / 2.0 + NODE_GAP).collect();
+
+            // Pushing right and pushing left both keep the order and spacing,
+            // their average stays as close as possible to the desired positions
+            let mut right = desired.clone();
+            for i in 1..right.len() {
+                right[i] = right[i].max(right[i - 1] + gaps[i - 1]);
+            }
+            let mut left = desired;
+            for i in (0..left.len().saturating_sub(1)).rev() {
+                left[i] = left[i].min(left[i + 1] - gaps[i]);
+            }
+            for (i, v) in layer.iter().enumerate() {
+                x[*v] = (left[i] + right[i]) / 2.0;
+            }
+        }
+    }
+
+    // y positions: each layer is as tall as its tallest node
+    let height = |v: usize| vertex_node[v].map_or(0.0, |i| size(i)[1]);
+    let layer_heights: Vec<f32> = layers.iter()
+        .map(|layer| layer.iter().map(|v| height(*v)).fold(0.0, f32::max))
+        .collect();
+    let mut layer_y = Vec::with_capacity(layer_count);
+    let mut y = 0.0;
+    for l in 0..layer_count {
+        if l > 0 {
+            y += (layer_heights[l - 1] + layer_heights[l]) / 2.0 + LAYER_GAP;
+        }
+        layer_y.push(y);
+    }
+    let sign = if cited_above { 1.0 } else { -1.0 };
+
+    let mut targets: Vec<[f32; 2]> = map.nodes.iter().map(|n| [n.x, n.y]).collect();
+    for (v, node) in vertex_node.iter().enumerate() {
+        if let Some(i) = node {
+            targets[*i] = [x[v], sign * layer_y[vertex_layer[v]]];
+        }
+    }
+
+    // Nodes without citations go in rows underneath the layers
+    let loose: Vec<usize> = (0..n).filter(|i| !connected[*i]).collect();
+    let mut row_y = if cited_above {
+        y + layer_heights.last().copied().unwrap_or(0.0) / 2.0
+    } else {
+        layer_heights.first().copied().unwrap_or(0.0) / 2.0
+    };
+    for row in loose.chunks(LOOSE_COLUMNS) {
+        let row_height = row.iter().map(|i| size(*i)[1]).fold(0.0, f32::max);
+        row_y += LAYER_GAP + row_height / 2.0;
+        let total: f32 = row.iter().map(|i| size(*i)[0]).sum::<f32>() + NODE_GAP * (row.len() - 1) as f32;
+        let mut left = -total / 2.0;
+        for &i in row {
+            targets[i] = [left + size(i)[0] / 2.0, row_y];
+            left += size(i)[0] + NODE_GAP;
+        }
+        row_y += row_height / 2.0;
+    }
+
+    // Keep the centre of the moved nodes where it was
+    let moving: Vec<usize> = (0..n).filter(|i| !map.nodes[*i].pinned).collect();
+    if moving.is_empty() {
+        return Vec::new();
+    }
+    let count = moving.len() as f32;
+    let centroid = |points: &dyn Fn(usize) -> [f32; 2]| {
+        let sum = moving.iter().fold([0.0, 0.0], |acc, i| [acc[0] + points(*i)[0], acc[1] + points(*i)[1]]);
+        [sum[0] / count, sum[1] / count]
+    };
+    let before = centroid(&|i| [map.nodes[i].x, map.nodes[i].y]);
+    let after = centroid(&|i| targets[i]);
+    let offset = [before[0] - after[0], before[1] - after[1]];
+
+    moving.into_iter()
+        .map(|i| {
+            let node = &map.nodes[i];
+            (node.id, [node.x, node.y], [targets[i][0] + offset[0], targets[i][1] + offset[1]])
+        })
+        .filter(|(_, from, to)| from != to)
+        .collect()
+}
+
+// Reverse the edges that close a cycle, found as back edges of a depth-first
+// search, so citation loops still get a sensible direction.
+fn remove_cycles(n: usize, edges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut outgoing: Vec<Vec<usize>> = vec![Vec::new(); n];
+    for &(a, b) in edges {
+        outgoing[a].push(b);
+    }
+
+    // 0 = unvisited, 1 = on the current path, 2 = done
+    let mut state = vec![0u8; n];
+    let mut back_edges = Vec::new();
+    for root in 0..n {
+        if state[root] != 0 {
+            continue;
+        }
+        let mut stack = vec![(root, 0usize)];
+        state[root] = 1;
+        while let Some((v, next)) = stack.pop() {
+            if let Some(&w) = outgoing[v].get(next) {
+                stack.push((v, next + 1));
+                match state[w] {
+                    0 => {
+                        state[w] = 1;
+                        stack.push((w, 0));
+                    }
+                    1 => back_edges.push((v, w)),
+                    _ => {}
+                }
+            } else {
+                state[v] = 2;
+            }
+        }
+    }
+
+    let mut result: Vec<(usize, usize)> = edges.iter()
+        .map(|&(a, b)| if back_edges.contains(&(a, b)) { (b, a) } else { (a, b) })
+        .collect();
+    result.sort_unstable();
+    result.dedup();
+    result
+}
+
+fn topological_order(n: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+    let mut incoming = vec![0usize; n];
+    for &(_, b) in edges {
+        incoming[b] += 1;
+    }
+    let mut ready: Vec<usize> = (0..n).filter(|i| incoming[*i] == 0).rev().collect();
+    let mut order = Vec::with_capacity(n);
+    while let Some(v) = ready.pop() {
+        order.push(v);
+        for &(a, b) in edges {
+            if a == v {
+                incoming[b] -= 1;
+                if incoming[b] == 0 {
+                    ready.push(b);
+                }
+            }
+        }
+    }
+    order
+}
+
+// Barycenter heuristic: alternately sort each layer by the mean position of
+// its neighbours in the layer above and below, keeping the best ordering seen.
+fn order_layers(mut layers: Vec<Vec<usize>>, upper: &[Vec<usize>], lower: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut best = layers.clone();
+    let mut best_crossings = count_crossings(&layers, lower);
+
+    for sweep in 0..ORDERING_SWEEPS {
+        let downwards = sweep % 2 == 0;
+        let count = layers.len();
+        for step in 1..count {
+            let l = if downwards { step } else { count - 1 - step };
+            let (fixed, neighbours) = if downwards { (l - 1, upper) } else { (l + 1, lower) };
+
+            let mut position = HashMap::new();
+            for (i, v) in layers[fixed].iter().enumerate() {
+                position.insert(*v, i as f32);
+            }
+            let mut keyed: Vec<(f32, usize)> = layers[l].iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let positions: Vec<f32> = neighbours[*v].iter().filter_map(|u| position.get(u).copied()).collect();
+                    let key = if positions.is_empty() { i as f32 } else { positions.iter().sum::<f32>() / positions.len() as f32 };
+                    (key, *v)
+                })
+                .collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            layers[l] = keyed.into_iter().map(|(_, v)| v).collect();
+        }
+
+        let crossings = count_crossings(&layers, lower);
+        if crossings < best_crossings {
+            best_crossings = crossings;
+            best = layers.clone();
+        }
+        if best_crossings == 0 {
+            break;
+        }
+    }
+    best
+}
+
+fn count_crossings(layers: &[Vec<usize>], lower: &[Vec<usize>]) -> usize {
+    let mut crossings = 0;
+    for pair in layers.windows(2) {
+        let position: HashMap<usize, usize> = pair[1].iter().enumerate().map(|(i, v)| (*v, i)).collect();
+        let segments: Vec<(usize, usize)> = pair[0].iter()
+            .enumerate()
+            .flat_map(|(i, v)| lower[*v].iter().filter_map(|w| position.get(w)).map(move |j| (i, *j)))
+            .collect();
+        for (k, a) in segments.iter().enumerate() {
+            for b in &segments[k + 1..] {
+                if (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1) {
+                    crossings += 1;
+                }
+            }
+        }
+    }
+    crossings
+}
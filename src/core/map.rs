@@ -15,6 +15,8 @@ pub struct Node {
     pub annotations: Vec<Annotation>,
     pub color: Option<[u8; 4]>,
     pub tags: Vec<Uuid>,
+    #[serde(default)]
+    pub pinned: bool, // automatic layouts leave pinned nodes where they are
 }
 
  #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -22,16 +24,124 @@ pub struct Node {
      pub id: Uuid,
      pub from: Uuid,
      pub to: Uuid,
-     pub edge_type: EdgeType,
+     pub edge_type: Uuid, // id of an `EdgeType` of the map
      pub annotations: Vec<Annotation>,
      pub color: Option<[u8; 4]>,
+     #[serde(default)]
+     pub label: Option<String>, // short text drawn on the edge
  }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
-pub enum EdgeType {
+/// A kind of relation between nodes, such as "supports" or "extends". Edge
+/// types are defined per project and give their edges a default look.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EdgeType {
+    pub id: Uuid,
+    pub name: String,
+    pub color: [u8; 4],    // used by edges without a color of their own
+    pub dash: DashStyle,
+    pub arrow: ArrowStyle, // only drawn on directed edges
+    pub directed: bool,    // undirected edges are followed both ways when finding paths
+}
+
+impl EdgeType {
+    /// Built-in types every map has. They can be restyled but not deleted;
+    /// References edges are what the layered layout and analytics treat as
+    /// citations.
+    pub const NORMAL: Uuid = Uuid::from_u128(1);
+    pub const REFERENCES: Uuid = Uuid::from_u128(2);
+
+    pub fn builtin() -> Vec<EdgeType> {
+        vec![
+            EdgeType {
+                id: EdgeType::NORMAL,
+                name: "Normal".to_string(),
+                color: [160, 160, 160, 255],
+                dash: DashStyle::Solid,
+                arrow: ArrowStyle::Open,
+                directed: false,
+            },
+            EdgeType {
+                id: EdgeType::REFERENCES,
+                name: "References".to_string(),
+                color: [160, 160, 160, 255],
+                dash: DashStyle::Solid,
+                arrow: ArrowStyle::Open,
+                directed: true,
+            },
+        ]
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        self.id == EdgeType::NORMAL || self.id == EdgeType::REFERENCES
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum DashStyle {
     #[default]
-    Normal,
-    References,
+    Solid,
+    Dashed,
+    Dotted,
+}
+
+impl DashStyle {
+    pub const ALL: [DashStyle; 3] = [DashStyle::Solid, DashStyle::Dashed, DashStyle::Dotted];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DashStyle::Solid => "Solid",
+            DashStyle::Dashed => "Dashed",
+            DashStyle::Dotted => "Dotted",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum ArrowStyle {
+    None,
+    #[default]
+    Open,
+    Filled,
+}
+
+impl ArrowStyle {
+    pub const ALL: [ArrowStyle; 3] = [ArrowStyle::None, ArrowStyle::Open, ArrowStyle::Filled];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ArrowStyle::None => "None",
+            ArrowStyle::Open => "Open",
+            ArrowStyle::Filled => "Filled",
+        }
+    }
+}
+
+/// A labeled box drawn around a group of nodes, such as "Baselines". Member
+/// nodes move with the frame, and a collapsed frame shows a single summary
+/// box in their place.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Frame {
+    pub id: Uuid,
+    pub label: String,
+    pub color: [u8; 4],
+    pub nodes: Vec<Uuid>, // members, in the order they were added
+    pub collapsed: bool,
+    pub annotations: Vec<Annotation>,
+}
+
+/// Background grid of a project. Dragged nodes snap to it when `snap` is set.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct GridSettings {
+    pub size: f32, // distance between grid lines, in canvas units
+    pub visible: bool,
+    pub snap: bool,
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        GridSettings { size: 40.0, visible: false, snap: false }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -43,11 +153,23 @@ pub struct Tag {
 
 
 
- #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+ #[derive(Debug, Clone, Serialize, Deserialize)]
  pub struct MindMap {
      pub nodes: Vec<Node>,
      pub edges: Vec<Edge>,
      pub(crate) tags: Vec<Tag>,
+     #[serde(default = "EdgeType::builtin")]
+     pub edge_types: Vec<EdgeType>,
+     #[serde(default)]
+     pub frames: Vec<Frame>,
+     #[serde(default)]
+     pub grid: GridSettings,
+ }
+
+ impl Default for MindMap {
+     fn default() -> Self {
+         MindMap { nodes: Vec::new(), edges: Vec::new(), tags: Vec::new(), edge_types: EdgeType::builtin(), frames: Vec::new(), grid: GridSettings::default() }
+     }
  }
 
  impl MindMap {
@@ -64,47 +186,53 @@ pub struct Tag {
              annotations: Vec::new(),
              color: None,
              tags: Vec::new(),
+             pinned: false,
          });
          id
      }
 
      pub fn add_pdf_node(&mut self, filename: &str, x: f32, y: f32) -> Result<Uuid, anyhow::Error> {
-         let metadata_result = Metadata::from_file(filename);
-         if let Ok(metadata) = metadata_result {
-             let title = if metadata.title.is_empty() {
-                 filename.to_string()
-             } else {
-                 metadata.title.clone()
-             };
-
-             let node = Node {
-                 id: Uuid::new_v4(),
-                 title,
-                 x,
-                 y,
-                 metadata: Some(metadata),
-                 collapsed: true,
-                 path: Some(filename.to_string()),
-                 annotations: Vec::new(),
-                 color: None,
-                 tags: Vec::new(),
-             };
-
-             let id = node.id;
-             self.nodes.push(node);
-             Ok(id)
-         }
-         else { Err(anyhow::anyhow!("Error running pdfinfo")) }
+         let metadata = Metadata::from_file(filename)?;
+         let title = if metadata.title.is_empty() {
+             filename.to_string()
+         } else {
+             metadata.title.clone()
+         };
+
+         let node = Node {
+             id: Uuid::new_v4(),
+             title,
+             x,
+             y,
+             metadata: Some(metadata),
+             collapsed: true,
+             path: Some(filename.to_string()),
+             annotations: Vec::new(),
+             color: None,
+             tags: Vec::new(),
+             pinned: false,
+         };
+
+         let id = node.id;
+         self.nodes.push(node);
+         Ok(id)
      }
 
      pub fn add_edge(&mut self, from: Uuid, to: Uuid) -> Uuid {
          let id = Uuid::new_v4();
-         self.edges.push(Edge { id, from, to, edge_type: Default::default(), annotations: Vec::new(), color: None });
+         self.edges.push(Edge { id, from, to, edge_type: EdgeType::NORMAL, annotations: Vec::new(), color: None, label: None });
          id
      }
 
+     pub fn edge_type(&self, id: Uuid) -> Option<&EdgeType> {
+         self.edge_types.iter().find(|t| t.id == id)
+     }
+
      pub fn remove_node(&mut self, node_id: Uuid) {
          self.nodes.retain(|n| n.id != node_id);
          self.edges.retain(|e| e.from != node_id && e.to != node_id);
+         for frame in &mut self.frames {
+             frame.nodes.retain(|id| *id != node_id);
+         }
      }
  }
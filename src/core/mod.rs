@@ -1,5 +1,17 @@
 pub mod map;
 pub mod storage;
 pub(crate) mod pdfparser;
+pub mod identifiers;
+pub mod undo;
+pub mod search;
+pub mod references;
+pub mod bibtex;
+pub mod ris;
+pub mod csl;
+pub mod layout;
+pub mod citations;
+pub mod duplicates;
+pub mod analytics;
+pub mod spatial;
 
 pub use map::MindMap;
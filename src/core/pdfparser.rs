@@ -1,21 +1,152 @@
 use std::process::Command;
+use lopdf::{Dictionary, Document};
 use serde::{Deserialize, Serialize};
+use crate::core::identifiers;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Headings that start a reference list, whose identifiers belong to other papers
+const REFERENCE_HEADINGS: [&str; 2] = ["references", "bibliography"];
+
+#[derive(Debug, Clone, Default, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Metadata {
     pub title : String,
     pub keywords: Vec<String>,
     pub authors: Vec<String>,
     pub date: String,
     pub path: String,
+    #[serde(default)]
+    pub doi: Option<String>,
+    #[serde(default)]
+    pub arxiv: Option<String>,          // arXiv identifier without version, e.g. "2001.08557"
+    #[serde(default)]
+    pub venue: Option<String>,          // journal or proceedings
+    #[serde(default)]
+    pub citation_key: Option<String>,   // BibTeX key the node was imported from
+    #[serde(default)]
+    pub entry_type: Option<String>,     // BibTeX entry type, e.g. "article" or "inproceedings"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub author_parts: Vec<PersonName>,  // family/given split of `authors` as imported
+}
+
+/// An author name split into given and family parts.
+#[derive(Debug, Clone, Default, PartialEq, Hash, Serialize, Deserialize)]
+pub struct PersonName {
+    pub given: String,
+    pub family: String,
+}
+
+impl PersonName {
+    /// Split a display name into parts. "Family, Given" is split on the comma;
+    /// otherwise the last word is the family name, together with any lowercase
+    /// particles such as "van" or "de" in front of it.
+    pub fn parse(name: &str) -> PersonName {
+        if let Some((family, given)) = name.split_once(',') {
+            return PersonName { given: given.trim().to_string(), family: family.trim().to_string() };
+        }
+
+        let words: Vec<&str> = name.split_whitespace().collect();
+        if words.len() < 2 {
+            return PersonName { given: String::new(), family: name.trim().to_string() };
+        }
+
+        let mut family_start = words.len() - 1;
+        while family_start > 1 && words[family_start - 1].chars().next().is_some_and(char::is_lowercase) {
+            family_start -= 1;
+        }
+        PersonName { given: words[..family_start].join(" "), family: words[family_start..].join(" ") }
+    }
+
+    /// "Given Family", the form authors are displayed and edited in.
+    pub fn display(&self) -> String {
+        format!("{} {}", self.given, self.family).trim().to_string()
+    }
 }
 
 impl Metadata {
+    pub fn set_authors(&mut self, names: Vec<PersonName>) {
+        self.authors = names.iter().map(PersonName::display).collect();
+        self.author_parts = names;
+    }
+
+    /// Whether `other` is the same paper according to a shared DOI or arXiv id.
+    pub fn same_identifier(&self, other: &Metadata) -> bool {
+        let same_doi = match (&self.doi, &other.doi) {
+            (Some(a), Some(b)) => identifiers::normalize_doi(a) == identifiers::normalize_doi(b),
+            _ => false,
+        };
+        same_doi || (self.arxiv.is_some() && self.arxiv == other.arxiv)
+    }
+
+    /// Authors split into given and family names. The imported split is used as
+    /// long as the author still reads the same, so hand-edited names fall back
+    /// to `PersonName::parse`.
+    pub fn author_names(&self) -> Vec<PersonName> {
+        self.authors.iter()
+            .map(|author| {
+                self.author_parts.iter()
+                    .find(|p| p.display() == *author)
+                    .cloned()
+                    .unwrap_or_else(|| PersonName::parse(author))
+            })
+            .collect()
+    }
+
+    /// Read the metadata of a PDF, falling back to `pdfinfo` when the file
+    /// cannot be parsed in-process.
     pub fn from_file(path: &str) -> Result<Metadata, anyhow::Error> {
+        match Self::from_pdf(path) {
+            Ok(metadata) => Ok(metadata),
+            Err(e) => {
+                eprintln!("Failed to parse PDF metadata natively ({}), trying pdfinfo", e);
+                Self::from_pdfinfo(path)
+            }
+        }
+    }
+
+    /// Parse the Info dictionary and XMP metadata stream of a PDF directly.
+    /// Values found in XMP take precedence over the Info dictionary. A DOI or
+    /// arXiv id missing from both is looked for in the text of the first page,
+    /// up to the reference list if a short paper starts it there.
+    pub fn from_pdf(path: &str) -> Result<Metadata, anyhow::Error> {
+        let doc = Document::load(path)?;
+
+        let mut metadata = Metadata {
+            path: path.to_string(),
+            ..Default::default()
+        };
+
+        let info = info_dictionary(&doc);
+        if let Some(info) = info {
+            metadata.apply_info(&doc, info);
+        }
+
+        let xmp = xmp_packet(&doc);
+        if let Some(xmp) = &xmp && let Err(e) = metadata.apply_xmp(xmp) {
+            eprintln!("Failed to parse XMP metadata: {}", e);
+        }
+
+        // dc:identifier, crossmark and publisher-specific fields all end up here
+        if let Some(xmp) = &xmp {
+            metadata.detect_identifiers(xmp);
+        }
+        if let Some(info) = info {
+            metadata.detect_identifiers(&info_text(&doc, info));
+        }
+        if metadata.doi.is_none() || metadata.arxiv.is_none() {
+            let pages: Vec<u32> = doc.get_pages().keys().take(1).copied().collect();
+            if let Ok(text) = doc.extract_text(&pages) {
+                metadata.detect_identifiers(before_references(&text));
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    /// Read metadata by running `pdfinfo` from poppler-utils.
+    pub fn from_pdfinfo(path: &str) -> Result<Metadata, anyhow::Error> {
         let output = Command::new("pdfinfo")
             .arg(path)
             .output()
-            .expect("Failed to run pdfinfo");
+            .map_err(|e| anyhow::anyhow!("Failed to run pdfinfo: {}", e))?;
 
         if !output.status.success() {
             eprintln!("Error running pdfinfo");
@@ -23,6 +154,8 @@ impl Metadata {
         }
 
         let stdout = String::from_utf8_lossy(&output.stdout);
+        let doi = identifiers::find_doi(&stdout);
+        let arxiv = identifiers::find_arxiv(&stdout);
 
         // Initialize fields
         let mut title = String::new();
@@ -34,15 +167,9 @@ impl Metadata {
             if let Some(rest) = line.strip_prefix("Title:") {
                 title = rest.trim().to_string();
             } else if let Some(rest) = line.strip_prefix("Author:") {
-                authors = rest
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .collect();
+                authors = split_list(rest);
             } else if let Some(rest) = line.strip_prefix("Keywords:") {
-                keywords = rest
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .collect();
+                keywords = split_list(rest);
             } else if let Some(rest) = line.strip_prefix("CreationDate:") {
                 date = rest.trim().to_string();
             }
@@ -54,6 +181,175 @@ impl Metadata {
             authors,
             date,
             path: path.to_string(),
+            doi,
+            arxiv,
+            ..Default::default()
         })
     }
-}
\ No newline at end of file
+
+    // Fill in a missing DOI or arXiv id from the first one mentioned in `text`
+    fn detect_identifiers(&mut self, text: &str) {
+        if self.doi.is_none() {
+            self.doi = identifiers::find_doi(text);
+        }
+        if self.arxiv.is_none() {
+            self.arxiv = identifiers::find_arxiv(text);
+        }
+    }
+
+    fn apply_info(&mut self, doc: &Document, info: &Dictionary) {
+        let text = |key: &[u8]| -> Option<String> {
+            let object = info.get_deref(key, doc).ok()?;
+            let value = lopdf::decode_text_string(object).ok()?;
+            let value = value.trim().to_string();
+            (!value.is_empty()).then_some(value)
+        };
+
+        if let Some(title) = text(b"Title") {
+            self.title = title;
+        }
+        if let Some(author) = text(b"Author") {
+            self.authors = split_list(&author);
+        }
+        if let Some(keywords) = text(b"Keywords") {
+            self.keywords = split_list(&keywords);
+        }
+        if let Some(date) = text(b"CreationDate") {
+            self.date = format_pdf_date(&date);
+        }
+    }
+
+    fn apply_xmp(&mut self, xmp: &str) -> Result<(), anyhow::Error> {
+        let options = roxmltree::ParsingOptions { allow_dtd: true, ..Default::default() };
+        let xml = roxmltree::Document::parse_with_options(xmp, options)?;
+
+        if let Some(title) = xmp_values(&xml, DC_NS, "title").into_iter().next() {
+            self.title = title;
+        }
+
+        let creators = xmp_values(&xml, DC_NS, "creator");
+        if !creators.is_empty() {
+            self.authors = creators;
+        }
+
+        let subjects: Vec<String> = xmp_values(&xml, DC_NS, "subject")
+            .iter()
+            .flat_map(|s| split_list(s))
+            .collect();
+        if !subjects.is_empty() {
+            self.keywords = subjects;
+        }
+
+        if let Some(date) = xmp_values(&xml, XMP_NS, "CreateDate").into_iter().next() {
+            self.date = date.chars().take(10).collect();
+        }
+
+        // prism:doi is used by most publishers, older files use the prism 2.0 namespace
+        if let Some(doi) = PRISM_NS.iter()
+            .flat_map(|ns| xmp_values(&xml, ns, "doi"))
+            .next()
+        {
+            self.doi = identifiers::find_doi(&doi).or(Some(doi));
+        }
+
+        Ok(())
+    }
+}
+
+const DC_NS: &str = "http://purl.org/dc/elements/1.1/";
+const XMP_NS: &str = "http://ns.adobe.com/xap/1.0/";
+const PRISM_NS: [&str; 3] = [
+    "http://prismstandard.org/namespaces/basic/3.0/",
+    "http://prismstandard.org/namespaces/basic/2.1/",
+    "http://prismstandard.org/namespaces/basic/2.0/",
+];
+
+// The text before a "References" or "7. Bibliography" line
+fn before_references(text: &str) -> &str {
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let heading = line.trim().trim_end_matches(':').to_lowercase();
+        let last_word = heading.split_whitespace().last().unwrap_or_default();
+        if heading.split_whitespace().count() <= 2 && REFERENCE_HEADINGS.contains(&last_word) {
+            return &text[..offset];
+        }
+        offset += line.len();
+    }
+    text
+}
+
+fn info_dictionary(doc: &Document) -> Option<&Dictionary> {
+    let info = doc.trailer.get(b"Info").ok()?;
+    let (_, info) = doc.dereference(info).ok()?;
+    info.as_dict().ok()
+}
+
+// Every text value of the Info dictionary, one per line
+fn info_text(doc: &Document, info: &Dictionary) -> String {
+    info.iter()
+        .filter_map(|(_, object)| {
+            let object = doc.dereference(object).ok()?.1;
+            lopdf::decode_text_string(object).ok()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn xmp_packet(doc: &Document) -> Option<String> {
+    let catalog = doc.catalog().ok()?;
+    let stream = catalog.get_deref(b"Metadata", doc).ok()?.as_stream().ok()?;
+    let content = stream.decompressed_content().unwrap_or_else(|_| stream.content.clone());
+    Some(String::from_utf8_lossy(&content).into_owned())
+}
+
+/// Collect the values of an XMP property, which may be written as a simple
+/// element, an `rdf:Alt`/`rdf:Seq`/`rdf:Bag` container or an attribute on
+/// `rdf:Description`.
+fn xmp_values(xml: &roxmltree::Document, namespace: &str, name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+
+    for node in xml.descendants() {
+        if node.is_element() && node.tag_name().namespace() == Some(namespace) && node.tag_name().name() == name {
+            let items: Vec<String> = node.descendants()
+                .filter(|n| n.is_element() && n.tag_name().name() == "li")
+                .filter_map(|n| n.text())
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+
+            if items.is_empty() {
+                if let Some(text) = node.text().map(str::trim).filter(|t| !t.is_empty()) {
+                    values.push(text.to_string());
+                }
+            } else {
+                values.extend(items);
+            }
+        }
+
+        if node.is_element() && let Some(value) = node.attribute((namespace, name)) && !value.trim().is_empty() {
+            values.push(value.trim().to_string());
+        }
+    }
+
+    values
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split([',', ';'])
+        .map(|s| s.trim())
+        .map(|s| s.strip_prefix("and ").unwrap_or(s).to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+// Turn a PDF date string ("D:20230115093000+01'00'") into "2023-01-15"
+fn format_pdf_date(date: &str) -> String {
+    let digits: String = date.trim_start_matches("D:").chars().take_while(|c| c.is_ascii_digit()).collect();
+    match digits.len() {
+        len if len >= 8 => format!("{}-{}-{}", &digits[0..4], &digits[4..6], &digits[6..8]),
+        len if len >= 6 => format!("{}-{}", &digits[0..4], &digits[4..6]),
+        4 => digits,
+        _ => date.to_string(),
+    }
+}
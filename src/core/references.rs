@@ -0,0 +1,239 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+use crate::core::map::{MindMap, Node};
+use crate::core::pdfparser::Metadata;
+use crate::core::undo::Command;
+use crate::core::{bibtex, csl, ris};
+
+// Layout of newly imported nodes
+const IMPORT_COLUMNS: usize = 5;
+const IMPORT_SPACING: [f32; 2] = [260.0, 90.0];
+
+/// A bibliography entry read from BibTeX, RIS or CSL-JSON.
+#[derive(Debug, Clone, Default)]
+pub struct Reference {
+    pub metadata: Metadata,   // citation_key is always set
+    pub pdf: Option<PathBuf>, // attached PDF that exists on disk
+}
+
+/// Bibliography file formats that can be imported and exported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    BibTeX,
+    Ris,
+    CslJson,
+}
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::BibTeX, Format::Ris, Format::CslJson];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::BibTeX => "BibTeX",
+            Format::Ris => "RIS",
+            Format::CslJson => "CSL-JSON",
+        }
+    }
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::BibTeX => &["bib"],
+            Format::Ris => &["ris"],
+            Format::CslJson => &["json"],
+        }
+    }
+
+    pub fn import_file(self, path: &Path) -> Result<Vec<Reference>> {
+        match self {
+            Format::BibTeX => bibtex::import_file(path),
+            Format::Ris => ris::import_file(path),
+            Format::CslJson => csl::import_file(path),
+        }
+    }
+
+    pub fn export(self, map: &MindMap, node_ids: Option<&[Uuid]>) -> Result<String> {
+        match self {
+            Format::BibTeX => Ok(bibtex::export(map, node_ids)),
+            Format::Ris => Ok(ris::export(map, node_ids)),
+            Format::CslJson => csl::export(map, node_ids),
+        }
+    }
+
+    pub fn export_file(self, map: &MindMap, node_ids: Option<&[Uuid]>, path: &Path) -> Result<()> {
+        fs::write(path, self.export(map, node_ids)?)?;
+        Ok(())
+    }
+}
+
+/// Build the commands that import `references` into `map`. References whose
+/// citation key, DOI or arXiv id matches an existing node update that node in
+/// place; the others become new nodes laid out in a grid starting at `origin`.
+/// Attached PDFs are copied into `pdfs_dir` when a project is open.
+pub fn import_commands(map: &MindMap, references: &[Reference], pdfs_dir: Option<&Path>, origin: [f32; 2]) -> Vec<Command> {
+    let mut commands = Vec::new();
+    let mut added = 0;
+
+    for reference in references {
+        let mut metadata = reference.metadata.clone();
+
+        let pdf = reference.pdf.as_ref().and_then(|pdf| match attach_pdf(pdf, pdfs_dir) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                eprintln!("Failed to attach {}: {}", pdf.display(), e);
+                None
+            }
+        });
+        if let Some(pdf) = &pdf {
+            metadata.path.clone_from(pdf);
+        }
+
+        let existing = map.nodes.iter().find(|n| {
+            let key = n.metadata.as_ref().and_then(|m| m.citation_key.as_deref());
+            key.is_some() && key == metadata.citation_key.as_deref()
+        });
+        let existing = existing.or_else(|| {
+            map.nodes.iter().find(|n| n.metadata.as_ref().is_some_and(|m| m.same_identifier(&metadata)))
+        });
+
+        if let Some(before) = existing {
+            let mut after = before.clone();
+            after.title = metadata.title.clone();
+            if after.path.is_none() {
+                after.path = pdf;
+            }
+            after.metadata = Some(metadata);
+            commands.push(Command::UpdateNode { before: before.clone(), after });
+        } else {
+            let x = origin[0] + (added % IMPORT_COLUMNS) as f32 * IMPORT_SPACING[0];
+            let y = origin[1] + (added / IMPORT_COLUMNS) as f32 * IMPORT_SPACING[1];
+            added += 1;
+
+            commands.push(Command::AddNode(Node {
+                id: Uuid::new_v4(),
+                title: metadata.title.clone(),
+                metadata: Some(metadata),
+                x,
+                y,
+                collapsed: true,
+                path: pdf,
+                annotations: Vec::new(),
+                color: None,
+                tags: Vec::new(),
+                pinned: false,
+            }));
+        }
+    }
+
+    commands
+}
+
+// Copy a PDF into the project's pdfs folder, or keep its absolute path when no
+// project is open yet (save_map copies it in on the first save).
+fn attach_pdf(pdf: &Path, pdfs_dir: Option<&Path>) -> Result<String> {
+    match pdfs_dir {
+        Some(pdfs_dir) => {
+            if !pdfs_dir.exists() {
+                fs::create_dir_all(pdfs_dir)?;
+            }
+            let file_name = pdf.file_name().ok_or_else(|| anyhow!("Invalid PDF path"))?;
+            let dest_path = pdfs_dir.join(file_name);
+            if !dest_path.exists() {
+                fs::copy(pdf, &dest_path)?;
+            }
+            Ok(dest_path.to_string_lossy().to_string())
+        }
+        None => Ok(fs::canonicalize(pdf)?.to_string_lossy().to_string()),
+    }
+}
+
+/// Gather the metadata of the nodes to export. When `node_ids` is given only
+/// those nodes are included. Every entry gets a unique citation key: imported
+/// entries keep theirs, other nodes get an `authoryearword` key that is stable
+/// as long as the metadata is. Tags are merged into the keywords.
+pub fn collect(map: &MindMap, node_ids: Option<&[Uuid]>) -> Vec<Metadata> {
+    let mut used_keys = HashSet::new();
+    let mut entries = Vec::new();
+
+    for node in &map.nodes {
+        if node_ids.is_some_and(|ids| !ids.contains(&node.id)) {
+            continue;
+        }
+
+        let mut metadata = node.metadata.clone().unwrap_or_default();
+        if metadata.title.is_empty() {
+            metadata.title = node.title.clone();
+        }
+
+        let base_key = metadata.citation_key.clone().unwrap_or_else(|| generate_key(&metadata));
+        let mut key = base_key.clone();
+        let mut collisions = 0;
+        while !used_keys.insert(key.clone()) {
+            key = format!("{}{}", base_key, key_suffix(collisions));
+            collisions += 1;
+        }
+        metadata.citation_key = Some(key);
+
+        // Tags are exported next to the keywords read from the paper itself
+        for tag_id in &node.tags {
+            if let Some(tag) = map.tags.iter().find(|t| t.id == *tag_id) && !tag.name.is_empty() && !metadata.keywords.contains(&tag.name) {
+                metadata.keywords.push(tag.name.clone());
+            }
+        }
+
+        entries.push(metadata);
+    }
+
+    entries
+}
+
+// Letters that tell colliding keys apart: a, b, ..., z, aa, ab, ...
+fn key_suffix(mut n: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push(b'a' + (n % 26) as u8);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap_or_default()
+}
+
+/// BibTeX entry type of an exported node, used as the common vocabulary that
+/// the RIS and CSL-JSON type names are mapped onto.
+pub fn entry_type(metadata: &Metadata) -> String {
+    metadata.entry_type.clone().unwrap_or_else(|| {
+        if metadata.venue.is_some() { "article".to_string() } else { "misc".to_string() }
+    })
+}
+
+/// The four-digit year at the start of a date such as "2023-01-15".
+pub fn year(date: &str) -> Option<String> {
+    let year: String = date.chars().skip_while(|c| !c.is_ascii_digit()).take_while(char::is_ascii_digit).collect();
+    (year.len() == 4).then_some(year)
+}
+
+/// An `authoryearword` citation key derived from the metadata.
+pub fn generate_key(metadata: &Metadata) -> String {
+    let author = metadata.author_names()
+        .into_iter()
+        .next()
+        .map(|name| name.family)
+        .unwrap_or_default();
+    let word = metadata.title
+        .split_whitespace()
+        .map(|w| w.chars().filter(|c| c.is_alphanumeric()).collect::<String>())
+        .find(|w| w.len() > 3)
+        .unwrap_or_default();
+
+    let key: String = format!("{}{}{}", author, year(&metadata.date).unwrap_or_default(), word)
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_lowercase();
+    if key.is_empty() { "untitled".to_string() } else { key }
+}
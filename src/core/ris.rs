@@ -0,0 +1,223 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use uuid::Uuid;
+use crate::core::identifiers;
+use crate::core::map::MindMap;
+use crate::core::pdfparser::{Metadata, PersonName};
+use crate::core::references::{self, Reference};
+
+// RIS type <-> BibTeX entry type, the first match wins on export
+const TYPES: [(&str, &str); 12] = [
+    ("JOUR", "article"),
+    ("CONF", "inproceedings"),
+    ("CPAPER", "inproceedings"),
+    ("BOOK", "book"),
+    ("CHAP", "incollection"),
+    ("THES", "phdthesis"),
+    ("RPRT", "techreport"),
+    ("ELEC", "online"),
+    ("GEN", "misc"),
+    ("EJOUR", "article"),
+    ("MGZN", "article"),
+    ("UNPB", "unpublished"),
+];
+
+/// A single `TY  - ` ... `ER  - ` record. Tags keep their order and may repeat.
+#[derive(Debug, Clone, Default)]
+pub struct RisRecord {
+    pub fields: Vec<(String, String)>,
+}
+
+impl RisRecord {
+    pub fn field(&self, tags: &[&str]) -> Option<&str> {
+        tags.iter().find_map(|tag| {
+            self.fields.iter()
+                .find(|(t, value)| t == tag && !value.is_empty())
+                .map(|(_, value)| value.as_str())
+        })
+    }
+
+    pub fn fields<'a>(&'a self, tags: &'a [&str]) -> impl Iterator<Item = &'a str> {
+        self.fields.iter()
+            .filter(|(t, value)| tags.contains(&t.as_str()) && !value.is_empty())
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Convert the record to node metadata. Records without an `ID` get a key
+    /// generated from the metadata, so importing the same file twice updates
+    /// the nodes instead of duplicating them.
+    pub fn to_metadata(&self) -> Metadata {
+        let entry_type = self.field(&["TY"]).map(|ty| {
+            TYPES.iter()
+                .find(|(ris, _)| *ris == ty)
+                .map(|(_, bib)| bib.to_string())
+                .unwrap_or_else(|| "misc".to_string())
+        });
+
+        let mut names: Vec<PersonName> = self.fields(&["AU", "A1"]).map(parse_name).collect();
+        if names.is_empty() {
+            names = self.fields(&["ED", "A2"]).map(parse_name).collect();
+        }
+
+        let keywords = self.fields(&["KW"])
+            .flat_map(|k| k.split(';'))
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+
+        // "2023/01/15/other info" or just "2023"
+        let date = self.field(&["DA", "PY", "Y1"])
+            .map(|date| {
+                date.split('/')
+                    .take(3)
+                    .filter(|part| !part.trim().is_empty())
+                    .collect::<Vec<_>>()
+                    .join("-")
+            })
+            .unwrap_or_default();
+
+        let mut metadata = Metadata {
+            title: self.field(&["TI", "T1", "CT"]).unwrap_or_default().to_string(),
+            keywords,
+            date,
+            doi: self.field(&["DO"]).map(str::to_string),
+            arxiv: self.fields(&["UR", "L2"]).find_map(identifiers::find_arxiv),
+            venue: self.field(&["T2", "JO", "JF", "JA", "BT", "PB"]).map(str::to_string),
+            entry_type,
+            ..Default::default()
+        };
+        metadata.set_authors(names);
+
+        let citation_key = self.field(&["ID"])
+            .map(str::to_string)
+            .unwrap_or_else(|| references::generate_key(&metadata));
+        if metadata.title.is_empty() {
+            metadata.title.clone_from(&citation_key);
+        }
+        metadata.citation_key = Some(citation_key);
+        metadata
+    }
+
+    /// The first PDF listed in an `L1` or `UR` field that exists on disk.
+    pub fn pdf_file(&self, ris_dir: &Path) -> Option<PathBuf> {
+        self.fields(&["L1", "UR"])
+            .filter(|link| link.to_lowercase().ends_with(".pdf"))
+            .map(|link| PathBuf::from(link.strip_prefix("file://").unwrap_or(link)))
+            .map(|path| if path.is_relative() { ris_dir.join(path) } else { path })
+            .find(|path| path.exists())
+    }
+}
+
+// "Oostvogels, Jonathan" -> Oostvogels / Jonathan
+fn parse_name(name: &str) -> PersonName {
+    match name.split_once(',') {
+        Some((family, given)) => PersonName { given: given.trim().to_string(), family: family.trim().to_string() },
+        None => PersonName { given: String::new(), family: name.trim().to_string() },
+    }
+}
+
+/// Parse every record in an RIS file. Lines that do not start with a tag are
+/// continuations of the previous value.
+pub fn parse(input: &str) -> Result<Vec<RisRecord>> {
+    let mut records = Vec::new();
+    let mut current: Option<RisRecord> = None;
+
+    for line in input.lines() {
+        let line = line.trim_start_matches('\u{feff}').trim_end();
+        let tag = parse_tag(line);
+
+        match (tag, current.as_mut()) {
+            (Some(("TY", value)), _) => {
+                if let Some(record) = current.take() {
+                    records.push(record);
+                }
+                current = Some(RisRecord { fields: vec![("TY".to_string(), value.to_string())] });
+            }
+            (Some(("ER", _)), Some(_)) => records.extend(current.take()),
+            (Some((tag, value)), Some(record)) => record.fields.push((tag.to_string(), value.to_string())),
+            (None, Some(record)) if !line.trim().is_empty() => {
+                if let Some((_, value)) = record.fields.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(record) = current {
+        return Err(anyhow!("Record '{}' is missing its ER line", record.field(&["TI", "T1"]).unwrap_or_default()));
+    }
+    Ok(records)
+}
+
+// "AU  - Oostvogels, Jonathan" -> ("AU", "Oostvogels, Jonathan")
+fn parse_tag(line: &str) -> Option<(&str, &str)> {
+    let tag = line.get(..2)?;
+    if !tag.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+        return None;
+    }
+    let rest = line[2..].trim_start_matches(' ').strip_prefix('-')?;
+    Some((tag, rest.trim()))
+}
+
+/// Read an RIS file as references, resolving PDFs relative to the file.
+pub fn import_file(path: &Path) -> Result<Vec<Reference>> {
+    let ris_dir = path.parent().unwrap_or(Path::new("."));
+    let references = parse(&fs::read_to_string(path)?)?
+        .iter()
+        .map(|record| Reference { metadata: record.to_metadata(), pdf: record.pdf_file(ris_dir) })
+        .collect();
+    Ok(references)
+}
+
+/// Write the nodes of `map` as RIS. When `node_ids` is given only those nodes
+/// are exported.
+pub fn export(map: &MindMap, node_ids: Option<&[Uuid]>) -> String {
+    let mut out = String::new();
+
+    for metadata in references::collect(map, node_ids) {
+        let entry_type = references::entry_type(&metadata);
+        let ty = TYPES.iter()
+            .find(|(_, bib)| *bib == entry_type)
+            .map(|(ris, _)| *ris)
+            .unwrap_or("GEN");
+
+        let mut fields: Vec<(&str, String)> = vec![("TY", ty.to_string())];
+        fields.push(("ID", metadata.citation_key.clone().unwrap_or_default()));
+        fields.push(("TI", metadata.title.clone()));
+        for name in metadata.author_names() {
+            let name = if name.given.is_empty() { name.family } else { format!("{}, {}", name.family, name.given) };
+            fields.push(("AU", name));
+        }
+        if let Some(year) = references::year(&metadata.date) {
+            fields.push(("PY", year));
+            if metadata.date.len() > 4 {
+                fields.push(("DA", metadata.date.replace('-', "/")));
+            }
+        }
+        if let Some(venue) = &metadata.venue {
+            fields.push(("T2", venue.clone()));
+        }
+        if let Some(doi) = &metadata.doi {
+            fields.push(("DO", doi.clone()));
+        }
+        if let Some(arxiv) = &metadata.arxiv {
+            fields.push(("UR", identifiers::arxiv_url(arxiv)));
+        }
+        for keyword in &metadata.keywords {
+            fields.push(("KW", keyword.clone()));
+        }
+        if !metadata.path.is_empty() {
+            fields.push(("L1", metadata.path.clone()));
+        }
+
+        for (tag, value) in fields {
+            out.push_str(&format!("{}  - {}\r\n", tag, value));
+        }
+        out.push_str("ER  - \r\n\r\n");
+    }
+
+    out
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use anyhow::Result;
+use lopdf::Document;
+use serde::{Deserialize, Serialize};
+
+pub const INDEX_FILE: &str = "search_index.json";
+
+// Characters of context shown on either side of a match
+const SNIPPET_CONTEXT: usize = 60;
+const MAX_RESULTS: usize = 200;
+
+/// Inverted index over the text of every PDF in a project's `pdfs` folder.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    documents: Vec<IndexedDocument>,
+    terms: HashMap<String, Vec<Posting>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IndexedDocument {
+    file: String,       // file name inside the pdfs folder
+    modified: u64,      // mtime in seconds, used to skip unchanged files on reindex
+    pages: Vec<String>, // extracted text per page, kept for snippets
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Posting {
+    document: usize,
+    page: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub file: String,
+    pub page: u32,      // 1-based page number
+    pub snippet: String,
+}
+
+impl SearchIndex {
+    pub fn load(project_dir: &str) -> Result<SearchIndex> {
+        let data = fs::read_to_string(Path::new(project_dir).join(INDEX_FILE))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// Write the index through a temporary file, so concurrent indexers and
+    /// readers never see a half-written `search_index.json`.
+    pub fn save(&self, project_dir: &str) -> Result<()> {
+        let data = serde_json::to_string(self)?;
+        let path = Path::new(project_dir).join(INDEX_FILE);
+        let temp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+        fs::write(&temp, data)?;
+        if let Err(e) = fs::rename(&temp, &path) {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Extract the text of every PDF under `<project_dir>/pdfs`. Documents whose
+    /// modification time matches an entry in `previous` are reused as-is.
+    pub fn build(project_dir: &str, previous: Option<SearchIndex>) -> Result<SearchIndex> {
+        let pdfs_dir = Path::new(project_dir).join("pdfs");
+        let mut previous: HashMap<String, IndexedDocument> = previous
+            .map(|index| index.documents.into_iter().map(|d| (d.file.clone(), d)).collect())
+            .unwrap_or_default();
+
+        let mut documents = Vec::new();
+        if pdfs_dir.exists() {
+            for entry in fs::read_dir(&pdfs_dir)? {
+                let path = entry?.path();
+                let is_pdf = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+                if !is_pdf {
+                    continue;
+                }
+
+                let file = path.file_name().unwrap().to_string_lossy().to_string();
+                let modified = fs::metadata(&path)?
+                    .modified()?
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+
+                if let Some(document) = previous.remove(&file) && document.modified == modified {
+                    documents.push(document);
+                    continue;
+                }
+
+                match extract_pages(&path) {
+                    Ok(pages) => documents.push(IndexedDocument { file, modified, pages }),
+                    Err(e) => eprintln!("Failed to index {}: {}", path.display(), e),
+                }
+            }
+        }
+        documents.sort_by(|a, b| a.file.cmp(&b.file));
+
+        let mut index = SearchIndex { documents, terms: HashMap::new() };
+        index.rebuild_terms();
+        Ok(index)
+    }
+
+    fn rebuild_terms(&mut self) {
+        self.terms.clear();
+        for (document, doc) in self.documents.iter().enumerate() {
+            for (page, text) in doc.pages.iter().enumerate() {
+                for term in tokenize(text) {
+                    let postings = self.terms.entry(term).or_default();
+                    let posting = Posting { document, page };
+                    if postings.last() != Some(&posting) {
+                        postings.push(posting);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Find the pages containing every word of `query`. Words match as
+    /// prefixes, so results update while the user is still typing.
+    pub fn search(&self, query: &str) -> Vec<SearchHit> {
+        let words = tokenize(query);
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches: Option<Vec<Posting>> = None;
+        for word in &words {
+            let mut postings: Vec<Posting> = self.terms.iter()
+                .filter(|(term, _)| term.starts_with(word.as_str()))
+                .flat_map(|(_, postings)| postings.iter().copied())
+                .collect();
+            postings.sort_by_key(|p| (p.document, p.page));
+            postings.dedup();
+
+            matches = Some(match matches {
+                None => postings,
+                Some(previous) => previous.into_iter().filter(|p| postings.contains(p)).collect(),
+            });
+        }
+
+        matches.unwrap_or_default()
+            .into_iter()
+            .take(MAX_RESULTS)
+            .map(|p| {
+                let doc = &self.documents[p.document];
+                SearchHit {
+                    file: doc.file.clone(),
+                    page: p.page as u32 + 1,
+                    snippet: snippet(&doc.pages[p.page], &words[0]),
+                }
+            })
+            .collect()
+    }
+
+    pub fn document_count(&self) -> usize {
+        self.documents.len()
+    }
+}
+
+/// Build the index for `project_dir` on a background thread. The previous
+/// on-disk index is reused for unchanged files and the result is written back
+/// next to `map.json` before being sent.
+pub fn spawn_indexer(project_dir: String) -> Receiver<Result<SearchIndex>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let previous = SearchIndex::load(&project_dir).ok();
+        let result = SearchIndex::build(&project_dir, previous).and_then(|index| {
+            index.save(&project_dir)?;
+            Ok(index)
+        });
+        let _ = sender.send(result);
+    });
+    receiver
+}
+
+pub(crate) fn extract_pages(path: &Path) -> Result<Vec<String>> {
+    let doc = Document::load(path)?;
+    let pages = doc.get_pages()
+        .keys()
+        .map(|number| doc.extract_text(&[*number]).unwrap_or_default())
+        .collect();
+    Ok(pages)
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 1)
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+fn snippet(text: &str, word: &str) -> String {
+    let lower = text.to_lowercase();
+    // Lowercasing can change byte offsets for some scripts, so fall back to the start
+    let position = lower.find(word).filter(|p| text.is_char_boundary(*p)).unwrap_or(0);
+
+    let mut start = position.saturating_sub(SNIPPET_CONTEXT);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (position + word.len() + SNIPPET_CONTEXT).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+
+    let mut snippet = text[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < text.len() {
+        snippet.push('…');
+    }
+    snippet
+}
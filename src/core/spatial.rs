@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// A box in canvas coordinates: [min x, min y, max x, max y].
+pub type Bounds = [f32; 4];
+
+// Most sample points taken along one line, so a line to a node far away
+// can't register itself in an unbounded number of cells
+const MAX_LINE_SAMPLES: usize = 10_000;
+
+/// A uniform grid over the canvas. Items are registered in the cells they
+/// cover and looked up by the cells a query covers, so finding what is under
+/// the pointer only checks items nearby instead of every item on the map.
+/// Items are replaced one at a time, so moving a node only touches its cells.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<Uuid>>,
+    items: HashMap<Uuid, Vec<(i32, i32)>>, // cells each item is registered in
+}
+
+impl Grid {
+    pub fn new(cell_size: f32) -> Grid {
+        Grid { cell_size, cells: HashMap::new(), items: HashMap::new() }
+    }
+
+    /// Register `id` in every cell `bounds` overlaps, replacing its previous cells.
+    pub fn insert_box(&mut self, id: Uuid, bounds: Bounds) {
+        let (min, max) = (self.cell(bounds[0], bounds[1]), self.cell(bounds[2], bounds[3]));
+        let cells = (min.0..=max.0).flat_map(|x| (min.1..=max.1).map(move |y| (x, y))).collect();
+        self.insert_cells(id, cells);
+    }
+
+    /// Register `id` in the cells a polyline passes through, replacing its
+    /// previous cells.
+    pub fn insert_line(&mut self, id: Uuid, points: &[[f32; 2]]) {
+        let step = self.cell_size / 4.0;
+        let mut cells = HashSet::new();
+        for segment in points.windows(2) {
+            let [a, b] = [segment[0], segment[1]];
+            let length = ((b[0] - a[0]).powi(2) + (b[1] - a[1]).powi(2)).sqrt();
+            let samples = ((length / step).ceil() as usize).clamp(1, MAX_LINE_SAMPLES);
+            for i in 0..=samples {
+                let t = i as f32 / samples as f32;
+                cells.insert(self.cell(a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t));
+            }
+        }
+        self.insert_cells(id, cells.into_iter().collect());
+    }
+
+    pub fn remove(&mut self, id: Uuid) {
+        for cell in self.items.remove(&id).into_iter().flatten() {
+            if let Some(ids) = self.cells.get_mut(&cell) {
+                ids.retain(|i| *i != id);
+                if ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// Items registered in any cell that `bounds` overlaps. Candidates only:
+    /// an item need not overlap `bounds` itself.
+    pub fn query(&self, bounds: Bounds) -> HashSet<Uuid> {
+        let (min, max) = (self.cell(bounds[0], bounds[1]), self.cell(bounds[2], bounds[3]));
+        let area = (max.0 as i64 - min.0 as i64 + 1) * (max.1 as i64 - min.1 as i64 + 1);
+        let in_range = |cell: &(i32, i32)| (min.0..=max.0).contains(&cell.0) && (min.1..=max.1).contains(&cell.1);
+
+        // A query larger than the occupied cells is answered by scanning those
+        if area > self.cells.len() as i64 {
+            self.cells.iter()
+                .filter(|(cell, _)| in_range(cell))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect()
+        } else {
+            (min.0..=max.0)
+                .flat_map(|x| (min.1..=max.1).map(move |y| (x, y)))
+                .filter_map(|cell| self.cells.get(&cell))
+                .flat_map(|ids| ids.iter().copied())
+                .collect()
+        }
+    }
+
+    fn insert_cells(&mut self, id: Uuid, cells: Vec<(i32, i32)>) {
+        self.remove(id);
+        for cell in &cells {
+            self.cells.entry(*cell).or_default().push(id);
+        }
+        self.items.insert(id, cells);
+    }
+
+    fn cell(&self, x: f32, y: f32) -> (i32, i32) {
+        ((x / self.cell_size).floor() as i32, (y / self.cell_size).floor() as i32)
+    }
+}
@@ -1,54 +1,293 @@
-use crate::core::map::MindMap;
+use crate::core::map::{EdgeType, GridSettings, MindMap};
+use crate::core::search::INDEX_FILE;
 use std::{fs, io};
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::Path;
 use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use toml::Value;
 use zip::write::{ExtendedFileOptions, FileOptions};
 use zip::ZipWriter;
 
+/// Version of the `map.json` schema written by this build. Bump it together
+/// with a new entry in `MIGRATIONS` whenever the shape of the file changes.
+pub const MAP_VERSION: u64 = 4;
+
+// MIGRATIONS[n] upgrades a version n file to version n + 1. Migrations get the
+// project directory for changes that depend on where the file lives.
+const MIGRATIONS: [fn(&mut serde_json::Value, &Path) -> Result<()>; MAP_VERSION as usize] = [
+    migrate_v0,
+    migrate_v1,
+    migrate_v2,
+    migrate_v3,
+];
+
+// Layout of map.json: the version key followed by the map itself
+#[derive(Serialize)]
+struct MapFile<'a> {
+    version: u64,
+    #[serde(flatten)]
+    map: &'a MindMap,
+}
+
+// Just the version key of a map.json, to check it without reading the map
+#[derive(Deserialize)]
+struct MapFileVersion {
+    #[serde(default)]
+    version: u64,
+}
+
+/// A map read from disk, together with the schema version it was upgraded from.
+pub struct LoadedMap {
+    pub map: MindMap,
+    pub migrated_from: Option<u64>,
+}
+
+/// Write `map.json`. PDFs outside the project are copied into its `pdfs`
+/// folder and every PDF path is stored relative to the project directory, so
+/// the project keeps working when it is moved. A `map.json` written by an older
+/// version is kept as `map.v<version>.json` before it is first overwritten.
 pub fn save_map(map: &MindMap, path: &str) -> Result<()> {
     let project_dir = std::path::Path::new(path);
     if !project_dir.exists() {
         fs::create_dir_all(project_dir)?;
     }
 
-    let json_path = project_dir.join("map.json");
-    let data = serde_json::to_string_pretty(map)?;
-    fs::write(&json_path, data)?;
-
     // Copy PDFs into the project directory's "pdfs" folder
     let pdfs_dir = project_dir.join("pdfs");
     if !pdfs_dir.exists() {
         fs::create_dir_all(&pdfs_dir)?;
     }
 
-    for node in &map.nodes {
-        if let Some(relative_path) = &node.path {
-            let original_path = std::path::Path::new(relative_path);
-            if original_path.is_relative() {
-                // This is a placeholder; actual implementation depends on tracking original paths
-                // For this example, assume relative paths are already correct
-                continue;
+    let mut stored = map.clone();
+    for node in &mut stored.nodes {
+        if let Some(pdf_path) = node.path.clone() {
+            let original_path = Path::new(&pdf_path);
+            let mut pdf_path = relative_to_project(&pdf_path, project_dir);
+            if Path::new(&pdf_path).is_absolute() {
+                let dest_path = pdfs_dir.join(original_path.file_name().unwrap_or_default());
+                // A missing PDF must not stop the map itself from being saved
+                if dest_path.exists() || fs::copy(original_path, &dest_path).is_ok() {
+                    pdf_path = relative_to_project(&dest_path.to_string_lossy(), project_dir);
+                } else {
+                    eprintln!("Failed to copy {} into the project", original_path.display());
+                }
             }
-            let file_name = original_path.file_name().unwrap().to_str().unwrap();
-            let dest_path = pdfs_dir.join(file_name);
-            if !dest_path.exists() {
-                std::fs::copy(original_path, &dest_path)?;
+            if let Some(metadata) = &mut node.metadata {
+                metadata.path.clone_from(&pdf_path);
             }
+            node.path = Some(pdf_path);
+        } else if let Some(metadata) = &mut node.metadata {
+            metadata.path = relative_to_project(&metadata.path, project_dir);
         }
     }
 
+    let json_path = project_dir.join("map.json");
+    if let Ok(existing) = fs::read_to_string(&json_path)
+        && let Ok(MapFileVersion { version }) = serde_json::from_str(&existing)
+        && version < MAP_VERSION
+    {
+        let backup_path = project_dir.join(format!("map.v{}.json", version));
+        if !backup_path.exists() {
+            fs::write(&backup_path, &existing)?;
+        }
+    }
+
+    let data = serde_json::to_string_pretty(&MapFile { version: MAP_VERSION, map: &stored })?;
+    fs::write(&json_path, data)?;
+
     Ok(())
 }
 
+/// Express `path` relative to `project_dir`, using forward slashes so the map
+/// can be shared between systems. Paths outside the project are returned as-is.
+pub fn relative_to_project(path: &str, project_dir: &Path) -> String {
+    let path = Path::new(path);
+    if path.is_relative() {
+        return path.to_string_lossy().replace('\\', "/");
+    }
+
+    let canonical_dir = fs::canonicalize(project_dir).ok();
+    let relative = path.strip_prefix(project_dir).ok()
+        .or_else(|| path.strip_prefix(canonical_dir.as_ref()?).ok());
+    match relative {
+        Some(relative) => relative.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+        None => path.to_string_lossy().to_string(),
+    }
+}
+
+/// Turn a path stored in `map.json` back into a full path inside `project_dir`.
+pub fn resolve_in_project(path: &str, project_dir: &Path) -> String {
+    if path.is_empty() || Path::new(path).is_absolute() {
+        path.to_string()
+    } else {
+        project_dir.join(path).to_string_lossy().to_string()
+    }
+}
+
 pub fn load_map(path: &str) -> Result<MindMap> {
+    Ok(load_map_file(path)?.map)
+}
+
+/// Load `map.json`, upgrading files written by older versions in memory; the
+/// file itself is left alone until the map is saved. Files from a newer
+/// version are rejected.
+pub fn load_map_file(path: &str) -> Result<LoadedMap> {
     let project_dir = std::path::Path::new(path);
     let json_path = project_dir.join("map.json");
     let data = fs::read_to_string(&json_path)?;
-    let map: MindMap = serde_json::from_str(&data)?;
-    Ok(map)
+    let mut value: serde_json::Value = serde_json::from_str(&data)?;
+
+    let version = migrate(&mut value, project_dir)?;
+    let mut map: MindMap = serde_json::from_value(value)?;
+
+    // Paths are kept absolute in memory so PDFs can be opened directly
+    for node in &mut map.nodes {
+        if let Some(path) = &mut node.path {
+            *path = resolve_in_project(path, project_dir);
+        }
+        if let Some(metadata) = &mut node.metadata {
+            metadata.path = resolve_in_project(&metadata.path, project_dir);
+        }
+    }
+
+    let migrated_from = (version < MAP_VERSION).then_some(version);
+    Ok(LoadedMap { map, migrated_from })
+}
+
+/// Run the migrations needed to bring `value` up to `MAP_VERSION` and return
+/// the version it started at. Files without a `version` key are version 0.
+pub fn migrate(value: &mut serde_json::Value, project_dir: &Path) -> Result<u64> {
+    let object = value.as_object_mut().ok_or_else(|| Error::msg("map.json is not an object"))?;
+    let version = match object.remove("version") {
+        None => 0,
+        Some(version) => version.as_u64().ok_or_else(|| Error::msg("map.json has an invalid version"))?,
+    };
+
+    if version > MAP_VERSION {
+        return Err(anyhow::anyhow!(
+            "map.json has version {}, but this version of RefMap only reads up to version {}. Please update RefMap.",
+            version,
+            MAP_VERSION
+        ));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(value, project_dir)?;
+    }
+    Ok(version)
+}
+
+// Version 0 files predate tags, colours and edge types and may lack any field
+// added since; fill in the defaults so they deserialize.
+fn migrate_v0(value: &mut serde_json::Value, _project_dir: &Path) -> Result<()> {
+    let object = value.as_object_mut().ok_or_else(|| Error::msg("map.json is not an object"))?;
+    for key in ["nodes", "edges", "tags"] {
+        object.entry(key).or_insert_with(|| json!([]));
+    }
+
+    let node_defaults = [
+        ("metadata", json!(null)),
+        ("collapsed", json!(false)),
+        ("path", json!(null)),
+        ("annotations", json!([])),
+        ("color", json!(null)),
+        ("tags", json!([])),
+    ];
+    for node in object["nodes"].as_array_mut().into_iter().flatten() {
+        if let Some(node) = node.as_object_mut() {
+            for (key, default) in &node_defaults {
+                node.entry(*key).or_insert_with(|| default.clone());
+            }
+        }
+    }
+
+    let edge_defaults = [
+        ("edge_type", json!("Normal")),
+        ("annotations", json!([])),
+        ("color", json!(null)),
+    ];
+    for edge in object["edges"].as_array_mut().into_iter().flatten() {
+        if let Some(edge) = edge.as_object_mut() {
+            for (key, default) in &edge_defaults {
+                edge.entry(*key).or_insert_with(|| default.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
+// Version 1 stored absolute PDF paths. Make them project-relative; paths
+// that point into the project's old location are found again by file name.
+fn migrate_v1(value: &mut serde_json::Value, project_dir: &Path) -> Result<()> {
+    let convert = |path: &str| -> String {
+        let relative = relative_to_project(path, project_dir);
+        if Path::new(&relative).is_absolute() && !Path::new(path).exists()
+            && let Some(file_name) = Path::new(path).file_name()
+            && project_dir.join("pdfs").join(file_name).exists()
+        {
+            return format!("pdfs/{}", file_name.to_string_lossy());
+        }
+        relative
+    };
+
+    for node in value["nodes"].as_array_mut().into_iter().flatten() {
+        if let Some(path) = node["path"].as_str() {
+            node["path"] = json!(convert(path));
+        }
+        if let Some(path) = node["metadata"]["path"].as_str() {
+            node["metadata"]["path"] = json!(convert(path));
+        }
+    }
+    Ok(())
+}
+
+// Version 2 had a fixed set of edge types stored by name. Types are now
+// defined in the map and edges refer to them by id.
+fn migrate_v2(value: &mut serde_json::Value, _project_dir: &Path) -> Result<()> {
+    let object = value.as_object_mut().ok_or_else(|| Error::msg("map.json is not an object"))?;
+    object.entry("edge_types").or_insert_with(|| json!(EdgeType::builtin()));
+
+    for edge in object.get_mut("edges").and_then(|edges| edges.as_array_mut()).into_iter().flatten() {
+        let edge_type = match edge["edge_type"].as_str() {
+            Some("References") => EdgeType::REFERENCES,
+            _ => EdgeType::NORMAL,
+        };
+        edge["edge_type"] = json!(edge_type);
+    }
+    Ok(())
+}
+
+// Version 3 files predate frames, per-project grid settings, edge labels,
+// pinned nodes, and the identifiers and BibTeX fields of node metadata.
+fn migrate_v3(value: &mut serde_json::Value, _project_dir: &Path) -> Result<()> {
+    let object = value.as_object_mut().ok_or_else(|| Error::msg("map.json is not an object"))?;
+    object.entry("frames").or_insert_with(|| json!([]));
+    object.entry("grid").or_insert_with(|| json!(GridSettings::default()));
+
+    for node in object.get_mut("nodes").and_then(|nodes| nodes.as_array_mut()).into_iter().flatten() {
+        if let Some(node) = node.as_object_mut() {
+            node.entry("pinned").or_insert(json!(false));
+            if let Some(metadata) = node.get_mut("metadata").and_then(|m| m.as_object_mut()) {
+                for key in ["doi", "arxiv", "venue", "citation_key", "entry_type"] {
+                    metadata.entry(key).or_insert(json!(null));
+                }
+                metadata.entry("author_parts").or_insert(json!([]));
+            }
+        }
+    }
+
+    for edge in object.get_mut("edges").and_then(|edges| edges.as_array_mut()).into_iter().flatten() {
+        if let Some(edge) = edge.as_object_mut() {
+            edge.entry("label").or_insert(json!(null));
+        }
+    }
+    Ok(())
 }
 
 fn get_config_dir() -> std::path::PathBuf {
@@ -115,6 +354,9 @@ pub fn export_project(project_dir: &str, zip_path: &str) -> Result<()> {
     for entry in walkdir::WalkDir::new(project_path).into_iter().filter_map(|e| e.ok()) {
         let path = entry.path();
         let name = path.strip_prefix(project_path)?.to_str().unwrap();
+        if skip_in_export(name) {
+            continue;
+        }
 
         if path.is_dir() {
             zip.add_directory(name, options.clone())?;
@@ -129,4 +371,63 @@ pub fn export_project(project_dir: &str, zip_path: &str) -> Result<()> {
 
     zip.finish()?;
     Ok(())
-}
\ No newline at end of file
+}
+
+// The search index is left out of exports since opening the project rebuilds
+// it, and so are the backups kept when an older map.json was upgraded
+fn skip_in_export(name: &str) -> bool {
+    let backup_version = name.strip_prefix("map.v").and_then(|rest| rest.strip_suffix(".json"));
+    name == INDEX_FILE || backup_version.is_some_and(|v| !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Unpack a zip written by `export_project` into `project_dir`, which must be
+/// empty or not exist yet. `map.json` is validated before anything is written
+/// and saved again afterwards, so its PDF paths point at the new location.
+pub fn import_project(zip_path: &str, project_dir: &str) -> Result<()> {
+    let project_path = Path::new(project_dir);
+    if project_path.exists() && fs::read_dir(project_path)?.next().is_some() {
+        return Err(anyhow::anyhow!("{} is not empty", project_dir));
+    }
+
+    let mut zip = zip::ZipArchive::new(File::open(zip_path)?)?;
+
+    // Archives zipped by hand usually wrap the project in a top-level folder
+    let map_entry = zip.file_names()
+        .filter(|name| *name == "map.json" || name.ends_with("/map.json"))
+        .min_by_key(|name| name.len())
+        .map(str::to_string)
+        .ok_or_else(|| Error::msg("The archive does not contain a map.json"))?;
+    let prefix = map_entry.trim_end_matches("map.json").to_string();
+
+    let mut data = String::new();
+    zip.by_name(&map_entry)?.read_to_string(&mut data)?;
+    let mut value: serde_json::Value = serde_json::from_str(&data)
+        .map_err(|e| anyhow::anyhow!("map.json in the archive is not valid JSON: {}", e))?;
+    migrate(&mut value, project_path)?;
+    serde_json::from_value::<MindMap>(value)
+        .map_err(|e| anyhow::anyhow!("map.json in the archive is not a valid map: {}", e))?;
+
+    fs::create_dir_all(project_path)?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        // enclosed_name rejects entries that would escape the project directory
+        let Some(name) = entry.enclosed_name() else { continue };
+        let Ok(name) = name.strip_prefix(&prefix) else { continue };
+        if name.as_os_str().is_empty() {
+            continue;
+        }
+
+        let dest_path = project_path.join(name);
+        if entry.is_dir() {
+            fs::create_dir_all(&dest_path)?;
+        } else {
+            if let Some(parent) = dest_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut entry, &mut File::create(&dest_path)?)?;
+        }
+    }
+
+    let loaded = load_map_file(project_dir)?;
+    save_map(&loaded.map, project_dir)
+}
@@ -0,0 +1,278 @@
+use uuid::Uuid;
+use crate::core::map::{Edge, EdgeType, Frame, MindMap, Node, Tag};
+
+// Maximum number of steps kept on the undo stack
+const HISTORY_LIMIT: usize = 200;
+
+/// A reversible change to a `MindMap`.
+///
+/// Every command stores enough state to both re-apply and revert itself, so the
+/// map never has to be snapshotted as a whole.
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum Command {
+    AddNode(Node),
+    RemoveNode { index: usize, node: Node, edges: Vec<(usize, Edge)>, frames: Vec<(Uuid, usize)> }, // frames: (frame, position among its members)
+    AddEdge(Edge),
+    RemoveEdge { index: usize, edge: Edge },
+    MoveNodes(Vec<(Uuid, [f32; 2], [f32; 2])>), // (node, from, to)
+    UpdateNode { before: Node, after: Node },
+    UpdateEdge { before: Edge, after: Edge },
+    AddTag(Tag),
+    UpdateTag { before: Tag, after: Tag },
+    AddEdgeType(EdgeType),
+    UpdateEdgeType { before: EdgeType, after: EdgeType },
+    RemoveEdgeType { index: usize, edge_type: EdgeType },
+    AddFrame(Frame),
+    UpdateFrame { before: Frame, after: Frame },
+    RemoveFrame { index: usize, frame: Frame },
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    /// Build a command that removes `node_id` together with all edges touching
+    /// it. The node also leaves the frames it belongs to, and frames left
+    /// without nodes are removed as well.
+    pub fn remove_node(map: &MindMap, node_id: Uuid) -> Option<Command> {
+        let index = map.nodes.iter().position(|n| n.id == node_id)?;
+        let edges = map.edges.iter()
+            .enumerate()
+            .filter(|(_, e)| e.from == node_id || e.to == node_id)
+            .map(|(i, e)| (i, e.clone()))
+            .collect();
+        let frames = map.frames.iter()
+            .filter_map(|f| Some((f.id, f.nodes.iter().position(|id| *id == node_id)?)))
+            .collect();
+        let remove = Command::RemoveNode { index, node: map.nodes[index].clone(), edges, frames };
+
+        // Removed last to first, so the indices of the others stay valid
+        let emptied: Vec<Command> = map.frames.iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, f)| f.nodes.iter().all(|id| *id == node_id) && !f.nodes.is_empty())
+            .map(|(index, f)| Command::RemoveFrame { index, frame: Frame { nodes: Vec::new(), ..f.clone() } })
+            .collect();
+        if emptied.is_empty() {
+            Some(remove)
+        } else {
+            Some(Command::Batch(std::iter::once(remove).chain(emptied).collect()))
+        }
+    }
+
+    /// Build a command that removes a single edge.
+    pub fn remove_edge(map: &MindMap, edge_id: Uuid) -> Option<Command> {
+        let index = map.edges.iter().position(|e| e.id == edge_id)?;
+        Some(Command::RemoveEdge { index, edge: map.edges[index].clone() })
+    }
+
+    /// Build a command that deletes a frame. Its nodes stay on the map.
+    pub fn remove_frame(map: &MindMap, frame_id: Uuid) -> Option<Command> {
+        let index = map.frames.iter().position(|f| f.id == frame_id)?;
+        Some(Command::RemoveFrame { index, frame: map.frames[index].clone() })
+    }
+
+    /// Build a command that deletes an edge type. Its edges become Normal
+    /// edges. Built-in types can't be deleted.
+    pub fn remove_edge_type(map: &MindMap, type_id: Uuid) -> Option<Command> {
+        let index = map.edge_types.iter().position(|t| t.id == type_id && !t.is_builtin())?;
+        let mut commands: Vec<Command> = map.edges.iter()
+            .filter(|e| e.edge_type == type_id)
+            .map(|before| {
+                let after = Edge { edge_type: EdgeType::NORMAL, ..before.clone() };
+                Command::UpdateEdge { before: before.clone(), after }
+            })
+            .collect();
+        commands.push(Command::RemoveEdgeType { index, edge_type: map.edge_types[index].clone() });
+        Some(Command::Batch(commands))
+    }
+
+    pub fn apply(&self, map: &mut MindMap) {
+        match self {
+            Command::AddNode(node) => map.nodes.push(node.clone()),
+            Command::RemoveNode { node, .. } => map.remove_node(node.id),
+            Command::AddEdge(edge) => map.edges.push(edge.clone()),
+            Command::RemoveEdge { edge, .. } => map.edges.retain(|e| e.id != edge.id),
+            Command::MoveNodes(moves) => {
+                for (id, _, to) in moves {
+                    if let Some(node) = map.nodes.iter_mut().find(|n| n.id == *id) {
+                        node.x = to[0];
+                        node.y = to[1];
+                    }
+                }
+            }
+            Command::UpdateNode { after, .. } => replace_node(map, after),
+            Command::UpdateEdge { after, .. } => replace_edge(map, after),
+            Command::AddTag(tag) => map.tags.push(tag.clone()),
+            Command::UpdateTag { after, .. } => replace_tag(map, after),
+            Command::AddEdgeType(edge_type) => map.edge_types.push(edge_type.clone()),
+            Command::UpdateEdgeType { after, .. } => replace_edge_type(map, after),
+            Command::RemoveEdgeType { edge_type, .. } => map.edge_types.retain(|t| t.id != edge_type.id),
+            Command::AddFrame(frame) => map.frames.push(frame.clone()),
+            Command::UpdateFrame { after, .. } => replace_frame(map, after),
+            Command::RemoveFrame { frame, .. } => map.frames.retain(|f| f.id != frame.id),
+            Command::Batch(commands) => {
+                for command in commands {
+                    command.apply(map);
+                }
+            }
+        }
+    }
+
+    pub fn revert(&self, map: &mut MindMap) {
+        match self {
+            Command::AddNode(node) => map.nodes.retain(|n| n.id != node.id),
+            Command::RemoveNode { index, node, edges, frames } => {
+                let index = (*index).min(map.nodes.len());
+                map.nodes.insert(index, node.clone());
+                // edges were recorded in ascending index order, so re-inserting
+                // them front to back restores the original ordering
+                for (edge_index, edge) in edges {
+                    let edge_index = (*edge_index).min(map.edges.len());
+                    map.edges.insert(edge_index, edge.clone());
+                }
+                for (frame_id, position) in frames {
+                    if let Some(frame) = map.frames.iter_mut().find(|f| f.id == *frame_id) {
+                        let position = (*position).min(frame.nodes.len());
+                        frame.nodes.insert(position, node.id);
+                    }
+                }
+            }
+            Command::AddEdge(edge) => map.edges.retain(|e| e.id != edge.id),
+            Command::RemoveEdge { index, edge } => {
+                let index = (*index).min(map.edges.len());
+                map.edges.insert(index, edge.clone());
+            }
+            Command::MoveNodes(moves) => {
+                for (id, from, _) in moves {
+                    if let Some(node) = map.nodes.iter_mut().find(|n| n.id == *id) {
+                        node.x = from[0];
+                        node.y = from[1];
+                    }
+                }
+            }
+            Command::UpdateNode { before, .. } => replace_node(map, before),
+            Command::UpdateEdge { before, .. } => replace_edge(map, before),
+            Command::AddTag(tag) => map.tags.retain(|t| t.id != tag.id),
+            Command::UpdateTag { before, .. } => replace_tag(map, before),
+            Command::AddEdgeType(edge_type) => map.edge_types.retain(|t| t.id != edge_type.id),
+            Command::UpdateEdgeType { before, .. } => replace_edge_type(map, before),
+            Command::RemoveEdgeType { index, edge_type } => {
+                let index = (*index).min(map.edge_types.len());
+                map.edge_types.insert(index, edge_type.clone());
+            }
+            Command::AddFrame(frame) => map.frames.retain(|f| f.id != frame.id),
+            Command::UpdateFrame { before, .. } => replace_frame(map, before),
+            Command::RemoveFrame { index, frame } => {
+                let index = (*index).min(map.frames.len());
+                map.frames.insert(index, frame.clone());
+            }
+            Command::Batch(commands) => {
+                for command in commands.iter().rev() {
+                    command.revert(map);
+                }
+            }
+        }
+    }
+}
+
+fn replace_node(map: &mut MindMap, node: &Node) {
+    if let Some(existing) = map.nodes.iter_mut().find(|n| n.id == node.id) {
+        *existing = node.clone();
+    }
+}
+
+fn replace_edge(map: &mut MindMap, edge: &Edge) {
+    if let Some(existing) = map.edges.iter_mut().find(|e| e.id == edge.id) {
+        *existing = edge.clone();
+    }
+}
+
+fn replace_tag(map: &mut MindMap, tag: &Tag) {
+    if let Some(existing) = map.tags.iter_mut().find(|t| t.id == tag.id) {
+        *existing = tag.clone();
+    }
+}
+
+fn replace_edge_type(map: &mut MindMap, edge_type: &EdgeType) {
+    if let Some(existing) = map.edge_types.iter_mut().find(|t| t.id == edge_type.id) {
+        *existing = edge_type.clone();
+    }
+}
+
+fn replace_frame(map: &mut MindMap, frame: &Frame) {
+    if let Some(existing) = map.frames.iter_mut().find(|f| f.id == frame.id) {
+        *existing = frame.clone();
+    }
+}
+
+/// Undo/redo stacks of `Command`s applied to a `MindMap`.
+#[derive(Debug, Default)]
+pub struct History {
+    undo_stack: Vec<Command>,
+    redo_stack: Vec<Command>,
+    generation: u64, // bumped on every change made through the history
+}
+
+impl History {
+    /// Apply `command` to the map and record it as a single undo step.
+    pub fn execute(&mut self, map: &mut MindMap, command: Command) {
+        command.apply(map);
+        self.record(command);
+    }
+
+    /// Record a command whose effect has already been applied to the map
+    /// (e.g. a node drag that moved the node frame by frame).
+    pub fn record(&mut self, command: Command) {
+        if matches!(&command, Command::Batch(commands) if commands.is_empty()) {
+            return;
+        }
+        self.undo_stack.push(command);
+        if self.undo_stack.len() > HISTORY_LIMIT {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+        self.generation += 1;
+    }
+
+    pub fn undo(&mut self, map: &mut MindMap) -> bool {
+        if let Some(command) = self.undo_stack.pop() {
+            command.revert(map);
+            self.redo_stack.push(command);
+            self.generation += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn redo(&mut self, map: &mut MindMap) -> bool {
+        if let Some(command) = self.redo_stack.pop() {
+            command.apply(map);
+            self.undo_stack.push(command);
+            self.generation += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Changes since the history was created, including undos and clears.
+    /// Views derived from the map compare it to know when to refresh.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.generation += 1;
+    }
+}
@@ -1,8 +1,15 @@
 mod core;
 mod app;
+mod cli;
 use crate::app::MindMapApp;
 
 fn main() -> eframe::Result<()> {
+    // Any argument selects the headless command-line interface
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
     let options = eframe::NativeOptions::default();
     eframe::run_native(
         "RefMap",